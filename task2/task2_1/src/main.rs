@@ -1,11 +1,11 @@
-use core::panic;
 use petgraph::dot::Dot;
 use petgraph::graph::DiGraph;
-use rand::seq::SliceRandom;
+use regex::{add_connect, tokenize, RegexError, Token};
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+mod regex;
 
 struct Graph_ {
     edges: Vec<Vec<(usize, char)>>,
@@ -21,7 +21,29 @@ impl Graph_ {
         self.edges[from].push((to, c));
     }
 
-    fn show_graph(&self, filename: &str) {
+    /// ε-closure of a set of points, sorted
+    pub fn epsilon_closure(&self, points: &[usize]) -> Vec<usize> {
+        let mut visited = vec![false; self.edges.len()];
+        let mut stack = Vec::new();
+        for &point in points {
+            if !visited[point] {
+                visited[point] = true;
+                stack.push(point);
+            }
+        }
+        while let Some(u) = stack.pop() {
+            for &(to, label) in &self.edges[u] {
+                if label == 'ε' && !visited[to] {
+                    visited[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+        (0..self.edges.len()).filter(|&i| visited[i]).collect()
+    }
+
+    /// 把 DOT 源码写入 dir 目录下的 filename，返回文件的路径
+    fn show_graph(&self, dir: &Path, filename: &str) -> Result<PathBuf, String> {
         let mut graph = DiGraph::new();
 
         // 添加节点
@@ -37,34 +59,63 @@ impl Graph_ {
             }
         }
 
-        let dot = Dot::new(&graph);
-
-        let path = Path::new("result_pic");
-        fs::create_dir_all(path).expect("Unable to create directory");
+        // petgraph 把字符标签输出为 'a'，去掉其中的引号
+        let dot = format!("{:?}", Dot::new(&graph)).replace('\'', "");
 
-        let dot_file_path = path.join(filename);
-        let dot_file_path_str = dot_file_path.to_str().unwrap();
-        std::fs::write(dot_file_path_str, format!("{:?}", dot)).expect("Unable to write file");
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        let dot_file = dir.join(filename);
+        fs::write(&dot_file, dot)
+            .map_err(|err| format!("failed to write {}: {}", dot_file.display(), err))?;
+        Ok(dot_file)
+    }
+}
 
-        std::process::Command::new("sed")
-            .args(&["-i", "s/'//g", dot_file_path_str])
-            .output()
-            .expect("Failed to execute sed command");
+/// 测试输出的 DOT 文件所在的目录
+const PICTURE_DIR: &str = "result_pic";
+
+/// 调用 Graphviz 把 DOT 文件渲染为同名的 PNG 图片，返回图片的路径
+///
+/// 没有安装 Graphviz 或渲染失败时返回错误信息，不会 panic
+fn render_dot(dot_file: &Path) -> Result<PathBuf, String> {
+    let output = dot_file.with_extension("png");
+    let result = std::process::Command::new("dot")
+        .arg("-Tpng")
+        .arg(dot_file)
+        .arg("-o")
+        .arg(&output)
+        .output();
+    match result {
+        Ok(result) if result.status.success() => Ok(output),
+        Ok(result) => Err(format!(
+            "dot failed to render {}: {}",
+            dot_file.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(format!(
+            "Graphviz `dot` was not found, install Graphviz to render {}",
+            dot_file.display()
+        )),
+        Err(err) => Err(format!("failed to run dot: {}", err)),
+    }
+}
 
-        let png_file_path = path.join(filename.replace(".dot", ".png"));
-        let png_file_path_str = png_file_path.to_str().unwrap();
-        std::process::Command::new("dot")
-            .args(&["-Tpng", dot_file_path_str, "-o", png_file_path_str])
-            .output()
-            .expect("Failed to execute dot command");
+/// 打印写出 DOT 文件时的错误，使用 `cargo run -- --render` 时再渲染为图片
+fn save_picture(dot_file: Result<PathBuf, String>) {
+    let render = std::env::args().skip(1).any(|arg| arg == "--render");
+    match dot_file {
+        Ok(dot_file) if render => match render_dot(&dot_file) {
+            Ok(png) => println!("rendered {}", png.display()),
+            Err(err) => println!("{}", err),
+        },
+        Ok(_) => {}
+        Err(err) => println!("{}", err),
     }
 }
 
 pub struct NFA {
     /// 确保起点为0，终点为 len - 1
     graph: Graph_,
-    /// 是否已经找到
-    has_succeed: bool,
 }
 
 impl Debug for NFA {
@@ -73,195 +124,161 @@ impl Debug for NFA {
     }
 }
 
-impl NFA {
-    pub fn new() -> NFA {
-        NFA {
-            graph: Graph_::new(),
-            has_succeed: false,
-        }
+impl Default for NFA {
+    fn default() -> Self {
+        NFA::new()
     }
+}
 
-    pub fn from(exp: &str) -> NFA {
-        let transfer_exp = add_connect(exp);
-        println!("transferd expression is {}", transfer_exp);
+impl TryFrom<&str> for NFA {
+    type Error = RegexError;
+
+    fn try_from(exp: &str) -> Result<Self, Self::Error> {
+        let tokens = add_connect(tokenize(exp)?);
+        let kinds: Vec<&Token> = tokens.iter().map(|(_, token)| token).collect();
+        println!("transferd expression is {:?}", kinds);
         let mut nfa_stack = Vec::new();
-        let mut op_stack = Vec::new();
-        // ( ) > * > + > |
-        for c in transfer_exp.chars() {
-            match c {
-                '(' => {
-                    op_stack.push(c);
-                }
-                ')' => {
-                    while let Some(op) = op_stack.pop() {
-                        if op == '(' {
-                            break;
-                        }
-                        let b = nfa_stack.pop().unwrap();
-                        let a = nfa_stack.pop().unwrap();
-                        match op {
-                            '*' => {
-                                nfa_stack.push(repeat(&a));
-                            }
-                            '|' => {
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '+' => {
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
-                        }
-                    }
-                }
-                '*' => {
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(repeat(&a));
+        let mut op_stack: Vec<(usize, Token)> = Vec::new();
+        // 下一个词法单元是否必须是操作数（字符或 `(`），用于发现 `*a`、`a|`、`()` 这类缺少操作数的写法
+        let mut expect_operand = true;
+        // ( ) > * + ? > Concat > |
+        for (offset, token) in tokens {
+            let is_operand = matches!(token, Token::Chars(_) | Token::LParen);
+            if expect_operand && !is_operand {
+                return Err(missing_operand(exp, offset));
+            }
+            expect_operand = matches!(token, Token::LParen | Token::Alt | Token::Concat);
+            match token {
+                Token::LParen => {
+                    op_stack.push((offset, token));
                 }
-                '+' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
+                Token::RParen => loop {
+                    match op_stack.pop() {
+                        Some((_, Token::LParen)) => break,
+                        Some((op_offset, op)) => apply(exp, op_offset, &op, &mut nfa_stack)?,
+                        None => {
+                            return Err(RegexError::new(
+                                exp,
+                                offset,
+                                "unmatched `)`",
+                                "an operator",
+                            ))
                         }
                     }
-                    op_stack.push(c);
+                },
+                Token::Star | Token::Plus | Token::Optional => {
+                    let a = nfa_stack
+                        .pop()
+                        .ok_or_else(|| missing_operand(exp, offset))?;
+                    nfa_stack.push(match token {
+                        Token::Star => repeat(&a),
+                        Token::Plus => and(&a, &repeat(&a)),
+                        _ => or(&a, &empty()),
+                    });
                 }
-                '|' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
+                Token::Concat | Token::Alt => {
+                    while let Some((op_offset, op)) = op_stack.pop() {
+                        if precedence(&op) < precedence(&token) {
+                            op_stack.push((op_offset, op));
+                            break;
                         }
+                        apply(exp, op_offset, &op, &mut nfa_stack)?;
                     }
-                    op_stack.push(c);
+                    op_stack.push((offset, token));
                 }
-                _ => {
-                    assert!(c.is_alphanumeric());
-                    nfa_stack.push(teminate(c));
+                Token::Chars(chars) => {
+                    nfa_stack.push(class(&chars));
                 }
             }
-            // println!("============ c is: {} ===============", c);
-            // println!("nfa_stack: {:?}", nfa_stack);
-            // println!("op_stack: {:?}", op_stack);
-            // println!("");
+        }
+        if expect_operand {
+            return Err(missing_operand(exp, exp.len()));
         }
 
-        while let Some(op) = op_stack.pop() {
-            match op {
-                '*' => {
-                    panic!("error: * can't be here");
-                }
-                '+' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(and(&a, &b));
-                }
-                '|' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(or(&a, &b));
-                }
-                '(' => {
-                    panic!("error: ( can't be here");
-                }
-                ')' => {
-                    panic!("error: ) can't be here");
-                }
-                _ => {
-                    panic!("error: unknown operator {}", op);
-                }
+        while let Some((offset, op)) = op_stack.pop() {
+            if op == Token::LParen {
+                return Err(RegexError::new(
+                    exp,
+                    offset,
+                    "unclosed `(`",
+                    "a matching `)`",
+                ));
             }
+            apply(exp, offset, &op, &mut nfa_stack)?;
         }
 
-        assert_eq!(nfa_stack.len(), 1);
-        assert_eq!(op_stack.len(), 0);
-        nfa_stack.pop().unwrap()
+        match (nfa_stack.pop(), nfa_stack.is_empty()) {
+            (Some(nfa), true) => Ok(nfa),
+            _ => Err(missing_operand(exp, exp.len())),
+        }
     }
+}
 
-    pub fn show(&self, id: usize) {
-        self.graph.show_graph(&format!("nfa_{}.dot", id));
+impl NFA {
+    pub fn new() -> NFA {
+        NFA {
+            graph: Graph_::new(),
+        }
     }
 
-    fn dfs(&mut self, u: usize, now_index: usize, exp: &str) -> bool {
-        // println!("u: {}, now_index: {}, exp: {}", u, now_index, exp);
-        if now_index == exp.len() {
-            if u == self.graph.edges.len() - 1 {
-                self.has_succeed = true;
-                return true;
-            }
-        }
-        if self.has_succeed {
-            return true;
-        }
-        let mut result = false;
+    /// 表达式不合法时 panic 并打印错误位置，需要处理错误时使用 `NFA::try_from`
+    pub fn from(exp: &str) -> NFA {
+        NFA::try_from(exp).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        let mut edges = self.graph.edges[u].clone();
-        let mut rng = rand::thread_rng();
-        edges.shuffle(&mut rng);
+    pub fn show(&self, dir: &Path, id: usize) -> Result<PathBuf, String> {
+        self.graph.show_graph(dir, &format!("nfa_{}.dot", id))
+    }
 
-        for (v, w) in &edges {
-            if *w == 'ε' {
-                result |= self.dfs(*v, now_index, exp);
-            }
-            if now_index == exp.len() {
-                continue;
-            }
-            if *w == exp.chars().nth(now_index).unwrap() {
-                result |= self.dfs(*v, now_index + 1, exp);
+    /// 同时维护所有可能到达的状态，每读入一个字符计算一次转移和 ε-closure，复杂度为 O(n·m)
+    pub fn contains(&self, exp: &str) -> bool {
+        let end = self.graph.edges.len() - 1;
+        let mut states = self.graph.epsilon_closure(&[0]);
+        for c in exp.chars() {
+            if states.is_empty() {
+                return false;
             }
+            let to_points: Vec<usize> = states
+                .iter()
+                .flat_map(|&u| &self.graph.edges[u])
+                .filter(|&&(_, label)| label == c)
+                .map(|&(to, _)| to)
+                .collect();
+            states = self.graph.epsilon_closure(&to_points);
         }
-        result
+        states.contains(&end)
     }
+}
 
-    pub fn contains(&mut self, exp: &str) -> bool {
-        self.has_succeed = false;
-        self.dfs(0, 0, exp)
+/// 二元运算符的优先级，`(` 只会被 `)` 弹出
+fn precedence(op: &Token) -> usize {
+    match op {
+        Token::Alt => 1,
+        Token::Concat => 2,
+        _ => 0,
     }
 }
 
-pub fn offset(edge: &Vec<(usize, char)>, offset: usize) -> Vec<(usize, char)> {
-    let mut result = Vec::new();
-    for i in 0..edge.len() {
-        result.push((edge[i].0 + offset, edge[i].1));
-    }
-    result
+fn missing_operand(exp: &str, offset: usize) -> RegexError {
+    RegexError::new(exp, offset, "missing operand", "a character or `(`")
+}
+
+/// pop two operands and apply the binary operator
+fn apply(exp: &str, offset: usize, op: &Token, nfa_stack: &mut Vec<NFA>) -> Result<(), RegexError> {
+    let (Some(b), Some(a)) = (nfa_stack.pop(), nfa_stack.pop()) else {
+        return Err(missing_operand(exp, offset));
+    };
+    nfa_stack.push(match op {
+        Token::Alt => or(&a, &b),
+        _ => and(&a, &b),
+    });
+    Ok(())
+}
+
+pub fn offset(edge: &[(usize, char)], offset: usize) -> Vec<(usize, char)> {
+    edge.iter()
+        .map(|&(to, label)| (to + offset, label))
+        .collect()
 }
 
 /// connect a and b
@@ -270,17 +287,12 @@ pub fn and(a: &NFA, b: &NFA) -> NFA {
     let b_edges = b.graph.edges.clone();
     let mut result = a_edges.clone();
 
-    for i in 0..b_edges.len() {
-        let mut tmp = Vec::new();
-        for j in 0..b_edges[i].len() {
-            tmp.push((b_edges[i][j].0 + a_edges.len(), b_edges[i][j].1));
-        }
-        result.push(tmp);
+    for edge in &b_edges {
+        result.push(offset(edge, a_edges.len()));
     }
     result[a_edges.len() - 1].push((a_edges.len(), 'ε'));
     NFA {
         graph: Graph_ { edges: result },
-        has_succeed: false,
     }
 }
 
@@ -311,7 +323,6 @@ pub fn or(a: &NFA, b: &NFA) -> NFA {
 
     NFA {
         graph: Graph_ { edges: result },
-        has_succeed: false,
     }
 }
 
@@ -338,46 +349,41 @@ pub fn repeat(a: &NFA) -> NFA {
 
     NFA {
         graph: Graph_ { edges: result },
-        has_succeed: false,
     }
 }
 
 /// teminate
 pub fn teminate(a: char) -> NFA {
-    let mut result = Vec::new();
-    result.push(vec![(1, a)]);
-    result.push(vec![]);
     NFA {
-        graph: Graph_ { edges: result },
-        has_succeed: false,
+        graph: Graph_ {
+            edges: vec![vec![(1, a)], vec![]],
+        },
     }
 }
 
-/// transfer the raw expression
-fn add_connect(exp: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = exp.chars().collect();
-    let len = chars.len();
-    for i in 0..len {
-        result.push(chars[i]);
-        if i + 1 < len {
-            let current = chars[i];
-            let next = chars[i + 1];
-            if (current.is_alphanumeric() || current == ')' || current == '*')
-                && (next.is_alphanumeric() || next == '(')
-            {
-                result.push('+');
-            }
-        }
+/// match any char of the class
+pub fn class(chars: &[char]) -> NFA {
+    if let [c] = chars {
+        return teminate(*c);
+    }
+    let edges = chars.iter().map(|&c| (1, c)).collect();
+    NFA {
+        graph: Graph_ {
+            edges: vec![edges, vec![]],
+        },
     }
-    result
+}
+
+/// match the empty string
+pub fn empty() -> NFA {
+    teminate('ε')
 }
 
 fn test1() {
     let regular_expression = "(a(ab|c))*d*";
     println!("regular expression: {}\n", regular_expression);
-    let mut nfa = NFA::from(&regular_expression);
-    nfa.show(1);
+    let nfa = NFA::from(regular_expression);
+    save_picture(nfa.show(Path::new(PICTURE_DIR), 1));
 
     let expression = "aabacacaabddd";
     println!("expression: {}", expression);
@@ -413,8 +419,8 @@ fn test1() {
 fn test2() {
     let regular_expression = "woc*";
     println!("regular expression: {}\n", regular_expression);
-    let mut nfa = NFA::from(&regular_expression);
-    nfa.show(2);
+    let nfa = NFA::from(regular_expression);
+    save_picture(nfa.show(Path::new(PICTURE_DIR), 2));
 
     let expression = "wocccccc";
     println!("expression: {}", expression);
@@ -425,8 +431,8 @@ fn test2() {
 fn test3() {
     let regular_expression = "(0|1)*101";
     println!("regular expression: {}\n", regular_expression);
-    let mut nfa = NFA::from(&regular_expression);
-    nfa.show(3);
+    let nfa = NFA::from(regular_expression);
+    save_picture(nfa.show(Path::new(PICTURE_DIR), 3));
 
     let expression = "11111111111111111000000101";
     println!("expression: {}", expression);
@@ -439,8 +445,32 @@ fn test3() {
     println!("identified: {}\n", identified);
 }
 
+fn test4() {
+    let regular_expression = r"-?[0-9]+(\.[0-9]+)?";
+    println!("regular expression: {}\n", regular_expression);
+    let nfa = NFA::from(regular_expression);
+    save_picture(nfa.show(Path::new(PICTURE_DIR), 4));
+
+    for expression in ["0", "-3.14", "1.", "a1"] {
+        println!("expression: {}", expression);
+        let identified = nfa.contains(expression);
+        println!("identified: {}\n", identified);
+    }
+}
+
+fn test5() {
+    for regular_expression in ["a|", "(ab", "ab)", "*a", "[z-a]", r"[a-\d]", "aεb"] {
+        match NFA::try_from(regular_expression) {
+            Ok(_) => println!("regular expression: {} is valid\n", regular_expression),
+            Err(err) => println!("{}\n", err),
+        }
+    }
+}
+
 fn main() {
     test1();
     test2();
     test3();
+    test4();
+    test5();
 }
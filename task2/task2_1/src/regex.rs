@@ -0,0 +1,248 @@
+use std::fmt::{Display, Formatter};
+
+/// 正规表达式的词法单元
+///
+/// 连接运算使用单独的 `Concat` 表示，不会与用户输入的 `+` 冲突
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// 单个字符、字符类或 `.`，已经展开为可以匹配的字符集合
+    Chars(Vec<char>),
+    LParen,
+    RParen,
+    Alt,
+    Concat,
+    Star,
+    Plus,
+    Optional,
+}
+
+/// 正规表达式的语法错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    pub pattern: String,
+    /// 出错位置在 `pattern` 中的字节偏移
+    pub offset: usize,
+    pub message: String,
+    /// 该位置期望出现的内容
+    pub expected: String,
+}
+
+impl RegexError {
+    pub fn new(pattern: &str, offset: usize, message: &str, expected: &str) -> Self {
+        RegexError {
+            pattern: pattern.to_string(),
+            offset,
+            message: message.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let column = self.pattern[..self.offset].chars().count();
+        writeln!(f, "regex error: {}", self.message)?;
+        writeln!(f, "  --> offset {}", self.offset)?;
+        writeln!(f, "   |")?;
+        writeln!(f, "   | {}", self.pattern)?;
+        writeln!(f, "   | {}^ expected {}", " ".repeat(column), self.expected)?;
+        write!(f, "   |")
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// `.` 和取反字符类所使用的字符全集
+pub fn universe() -> Vec<char> {
+    let mut result = vec!['\t', '\n', '\r'];
+    result.extend(' '..='~');
+    result
+}
+
+/// 逐字符读取正规表达式，并记录每个字符的字节偏移
+struct Scanner<'a> {
+    pattern: &'a str,
+    chars: Vec<(usize, char)>,
+    index: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(pattern: &'a str) -> Self {
+        Scanner {
+            pattern,
+            chars: pattern.char_indices().collect(),
+            index: 0,
+        }
+    }
+
+    /// 下一个字符的字节偏移，读完时为 `pattern` 的长度
+    fn offset(&self) -> usize {
+        self.chars
+            .get(self.index)
+            .map_or(self.pattern.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).map(|&(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.index += 1;
+        }
+        c
+    }
+
+    fn error(&self, offset: usize, message: &str, expected: &str) -> RegexError {
+        RegexError::new(self.pattern, offset, message, expected)
+    }
+
+    /// 解析 `\` 之后的转义序列
+    fn parse_escape(&mut self) -> Result<Vec<char>, RegexError> {
+        let offset = self.offset();
+        let c = self.next().ok_or_else(|| {
+            self.error(offset, "dangling escape at the end", "an escaped character")
+        })?;
+        Ok(match c {
+            'n' => vec!['\n'],
+            't' => vec!['\t'],
+            'r' => vec!['\r'],
+            'd' => ('0'..='9').collect(),
+            'w' => universe()
+                .into_iter()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect(),
+            's' => vec![' ', '\t', '\n', '\r'],
+            _ => vec![c],
+        })
+    }
+
+    /// 解析 `[...]` 字符类，`[` 已经被读取
+    fn parse_class(&mut self) -> Result<Vec<char>, RegexError> {
+        let negate = self.peek() == Some('^');
+        if negate {
+            self.index += 1;
+        }
+
+        let mut set = Vec::new();
+        let mut first = true;
+        loop {
+            let offset = self.offset();
+            let c = self
+                .next()
+                .ok_or_else(|| self.error(offset, "unclosed character class", "`]`"))?;
+            // 第一个字符为 `]` 时视为普通字符
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let low = if c == '\\' {
+                let escaped = self.parse_escape()?;
+                if escaped.len() != 1 {
+                    set.extend(escaped);
+                    continue;
+                }
+                escaped[0]
+            } else {
+                c
+            };
+
+            let is_range = self.peek() == Some('-')
+                && self
+                    .chars
+                    .get(self.index + 1)
+                    .is_some_and(|&(_, c)| c != ']');
+            if is_range {
+                self.index += 1;
+                let high_offset = self.offset();
+                let mut high = self.next().unwrap();
+                if high == '\\' {
+                    // `\d` 这类表示多个字符的转义不能作为区间的端点
+                    high = match self.parse_escape()?.as_slice() {
+                        [c] => *c,
+                        _ => {
+                            return Err(self.error(
+                                high_offset,
+                                "invalid range end",
+                                "a single character",
+                            ))
+                        }
+                    };
+                }
+                if low > high {
+                    return Err(self.error(
+                        high_offset,
+                        &format!("invalid range {}-{}", low, high),
+                        &format!("a character not less than `{}`", low),
+                    ));
+                }
+                set.extend(low..=high);
+            } else {
+                set.push(low);
+            }
+        }
+
+        if negate {
+            set = universe()
+                .into_iter()
+                .filter(|c| !set.contains(c))
+                .collect();
+        }
+        set.sort();
+        set.dedup();
+        Ok(set)
+    }
+}
+
+/// 将原始的正规表达式切分为词法单元，每个单元附带其字节偏移
+pub fn tokenize(exp: &str) -> Result<Vec<(usize, Token)>, RegexError> {
+    let mut scanner = Scanner::new(exp);
+    let mut result = Vec::new();
+    while let Some(c) = scanner.peek() {
+        let offset = scanner.offset();
+        scanner.index += 1;
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '|' => Token::Alt,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '?' => Token::Optional,
+            '.' => Token::Chars(universe().into_iter().filter(|&c| c != '\n').collect()),
+            '[' => Token::Chars(scanner.parse_class()?),
+            '\\' => Token::Chars(scanner.parse_escape()?),
+            _ => Token::Chars(vec![c]),
+        };
+        // ε 转移用字符 `ε` 表示，表达式中出现的 `ε` 会被当成空串，因此不允许使用
+        if matches!(&token, Token::Chars(chars) if chars.contains(&'ε')) {
+            return Err(scanner.error(
+                offset,
+                "`ε` is reserved for ε-moves",
+                "a character other than `ε`",
+            ));
+        }
+        result.push((offset, token));
+    }
+    Ok(result)
+}
+
+/// 在需要连接的两个词法单元之间插入 `Concat`，其偏移为右侧单元的偏移
+pub fn add_connect(tokens: Vec<(usize, Token)>) -> Vec<(usize, Token)> {
+    let mut result: Vec<(usize, Token)> = Vec::new();
+    for (offset, token) in tokens {
+        if let Some((_, prev)) = result.last() {
+            let left = matches!(
+                prev,
+                Token::Chars(_) | Token::RParen | Token::Star | Token::Plus | Token::Optional
+            );
+            let right = matches!(token, Token::Chars(_) | Token::LParen);
+            if left && right {
+                result.push((offset, Token::Concat));
+            }
+        }
+        result.push((offset, token));
+    }
+    result
+}
@@ -1,5 +1,9 @@
 use crate::{graph::Graph_, nfa::NFA};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// 生成例子时最多检查的串的个数，避免在很大的字母表上无限展开
+const MAX_EXAMPLES_VISITED: usize = 2000;
 
 pub struct DFA {
     start_state: usize,
@@ -16,7 +20,9 @@ impl DFA {
         let mut states = HashSet::new();
         states.insert(start_state.clone());
         graph.dfs_get_dfa_states(&start_state, &terminal, &mut states);
-        let states = states.into_iter().collect::<Vec<_>>();
+        // 按NFA状态集合排序后编号，使每次运行得到的DFA和DOT文件相同
+        let mut states = states.into_iter().collect::<Vec<_>>();
+        states.sort();
         let mut index_of_state = HashMap::new();
         for (i, state) in states.iter().enumerate() {
             index_of_state.insert(state, i);
@@ -26,7 +32,7 @@ impl DFA {
         for u in states.iter() {
             for &w in &terminal {
                 let v = graph.extend_from_point_vec_and_val(u, w);
-                if v.len() != 0 {
+                if !v.is_empty() {
                     graph_res.add_edge(
                         *index_of_state.get(u).unwrap(),
                         *index_of_state.get(&v).unwrap(),
@@ -41,20 +47,83 @@ impl DFA {
 
         DFA {
             start_state: *index_of_state.get(&start_state).unwrap(),
-            end_states: end_states,
+            end_states,
             graph: graph_res,
         }
     }
 
-    pub fn show(&self, id: usize) {
+    pub fn show(&self, dir: &Path, id: usize) -> Result<PathBuf, String> {
         println!("start state: {}", self.start_state);
         println!("end states: {:?}", self.end_states);
-        self.graph.show_graph(&format!("dfa_{}.dot", id));
+        self.graph.show_graph(dir, &format!("dfa_{}.dot", id))
     }
 
     pub fn contains(&self, exp: &str) -> bool {
-        let mut succeed = false;
-        self.graph
-            .dfs_search(self.start_state, &self.end_states, 0, exp, &mut succeed)
+        self.graph.simulate(self.start_state, &self.end_states, exp)
+    }
+
+    /// 从 state 读入 c 之后到达的状态，没有转移时返回 `None`
+    fn next_state(&self, state: usize, c: char) -> Option<usize> {
+        self.graph.edges[state]
+            .iter()
+            .find(|&&(_, label)| label == c)
+            .map(|&(to, _)| to)
+    }
+
+    /// 每个字符等价类的代表字符：在所有状态上转移都相同的字符只保留一个，优先取可见字符
+    fn representatives(&self) -> Vec<char> {
+        let mut classes: Vec<(Vec<Option<usize>>, char)> = Vec::new();
+        for c in self.graph.get_terminal() {
+            let column: Vec<Option<usize>> = (0..self.graph.edges.len())
+                .map(|state| self.next_state(state, c))
+                .collect();
+            match classes.iter_mut().find(|(other, _)| *other == column) {
+                Some((_, representative)) => {
+                    if !representative.is_ascii_graphic() && c.is_ascii_graphic() {
+                        *representative = c;
+                    }
+                }
+                None => classes.push((column, c)),
+            }
+        }
+        let mut result: Vec<char> = classes.into_iter().map(|(_, c)| c).collect();
+        result.sort();
+        result
+    }
+
+    /// 按 shortlex 顺序（先按长度，再按字典序）找出最短的 count 个被接受的串和 count 个不被接受的串
+    ///
+    /// 只使用每个字符等价类的代表字符，最多检查 `MAX_EXAMPLES_VISITED` 个串，因此可能不足 count 个
+    pub fn examples(&self, count: usize) -> (Vec<String>, Vec<String>) {
+        let alphabet = self.representatives();
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        // 状态为 `None` 表示已经没有转移，之后得到的串都不被接受
+        let mut queue = VecDeque::from([(Some(self.start_state), String::new())]);
+        let mut visited = 0;
+        while let Some((state, text)) = queue.pop_front() {
+            visited += 1;
+            if visited > MAX_EXAMPLES_VISITED {
+                break;
+            }
+            let is_accept = state.is_some_and(|state| self.end_states.contains(&state));
+            if is_accept && accepted.len() < count {
+                accepted.push(text.clone());
+            } else if !is_accept && rejected.len() < count {
+                rejected.push(text.clone());
+            }
+            if accepted.len() == count && rejected.len() == count {
+                break;
+            }
+            // 从死状态出发只能得到不被接受的串
+            if state.is_none() && rejected.len() == count {
+                continue;
+            }
+            for &c in &alphabet {
+                let next = state.and_then(|state| self.next_state(state, c));
+                queue.push_back((next, format!("{}{}", text, c)));
+            }
+        }
+        (accepted, rejected)
     }
 }
@@ -1,9 +1,8 @@
 use petgraph::dot::Dot;
 use petgraph::graph::DiGraph;
-use rand::seq::SliceRandom;
 use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct Graph_ {
     pub edges: Vec<Vec<(usize, char)>>,
@@ -22,81 +21,58 @@ impl Graph_ {
         self.edges[from].push((to, label));
     }
 
-    fn dfs_only_epsilon(&self, point: usize, visited: &mut Vec<bool>, result: &mut Vec<usize>) {
-        visited[point] = true;
-        for &(to, label) in &self.edges[point] {
-            if label == 'ε' && !visited[to] {
-                result.push(to);
-                self.dfs_only_epsilon(to, visited, result);
+    /// ε-closure of a set of points, sorted
+    pub fn epsilon_closure(&self, points: &[usize]) -> Vec<usize> {
+        let mut visited = vec![false; self.edges.len()];
+        let mut stack = Vec::new();
+        for &point in points {
+            if !visited[point] {
+                visited[point] = true;
+                stack.push(point);
             }
         }
+        while let Some(u) = stack.pop() {
+            for &(to, label) in &self.edges[u] {
+                if label == 'ε' && !visited[to] {
+                    visited[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+        (0..self.edges.len()).filter(|&i| visited[i]).collect()
     }
 
     pub fn extend_from_point(&self, point: usize) -> Vec<usize> {
-        let mut result = Vec::new();
-        result.push(point);
-        let mut visited = vec![false; self.edges.len()];
-        self.dfs_only_epsilon(point, &mut visited, &mut result);
-        result
+        self.epsilon_closure(&[point])
     }
 
-    pub fn dfs_search(
-        &self,
-        u: usize,
-        end_states: &Vec<usize>,
-        now_index: usize,
-        exp: &str,
-        has_succeed: &mut bool,
-    ) -> bool {
-        // println!("u: {}, now_index: {}, exp: {}", u, now_index, exp);
-        if now_index == exp.len() {
-            if end_states.contains(&u) {
-                *has_succeed = true;
-                return true;
+    /// 同时维护所有可能到达的状态，每读入一个字符计算一次转移和 ε-closure，复杂度为 O(n·m)
+    pub fn simulate(&self, start_state: usize, end_states: &[usize], exp: &str) -> bool {
+        let mut states = self.extend_from_point(start_state);
+        for c in exp.chars() {
+            if states.is_empty() {
+                return false;
             }
+            states = self.extend_from_point_vec_and_val(&states, c);
         }
-        if *has_succeed {
-            return true;
-        }
-        let mut result = false;
-
-        let mut edges = self.edges[u].clone();
-        let mut rng = rand::thread_rng();
-        edges.shuffle(&mut rng);
-
-        for (v, w) in &edges {
-            if *w == 'ε' {
-                result |= self.dfs_search(*v, end_states, now_index, exp, has_succeed);
-            }
-            if now_index == exp.len() {
-                continue;
-            }
-            if *w == exp.chars().nth(now_index).unwrap() {
-                result |= self.dfs_search(*v, end_states, now_index + 1, exp, has_succeed);
-            }
-        }
-        result
+        states.iter().any(|state| end_states.contains(state))
     }
 
-    pub fn extend_from_point_vec_and_val(&self, point: &Vec<usize>, val: char) -> Vec<usize> {
-        let mut result = HashSet::new();
+    pub fn extend_from_point_vec_and_val(&self, point: &[usize], val: char) -> Vec<usize> {
+        let mut to_points = Vec::new();
         for &point in point {
-            for (to, label) in &self.edges[point] {
-                if *label == val {
-                    for x in self.extend_from_point(*to) {
-                        result.insert(x);
-                    }
+            for &(to, label) in &self.edges[point] {
+                if label == val {
+                    to_points.push(to);
                 }
             }
         }
-        let mut to_state: Vec<usize> = result.clone().into_iter().collect();
-        to_state.sort();
-        to_state
+        self.epsilon_closure(&to_points)
     }
 
     pub fn dfs_get_dfa_states(
         &self,
-        u: &Vec<usize>,
+        u: &[usize],
         terminate_states: &Vec<char>,
         states: &mut HashSet<Vec<usize>>,
     ) {
@@ -122,7 +98,8 @@ impl Graph_ {
         res
     }
 
-    pub fn show_graph(&self, filename: &str) {
+    /// 把 DOT 源码写入 dir 目录下的 filename，返回文件的路径
+    pub fn show_graph(&self, dir: &Path, filename: &str) -> Result<PathBuf, String> {
         let mut graph = DiGraph::new();
 
         let mut node_indices = vec![];
@@ -135,25 +112,40 @@ impl Graph_ {
             }
         }
 
-        let dot = Dot::new(&graph);
+        // petgraph 把字符标签输出为 'a'，去掉其中的引号
+        let dot = format!("{:?}", Dot::new(&graph)).replace('\'', "");
 
-        let path = Path::new("result_pic");
-        fs::create_dir_all(path).expect("Unable to create directory");
-
-        let dot_file_path = path.join(filename);
-        let dot_file_path_str = dot_file_path.to_str().unwrap();
-        std::fs::write(dot_file_path_str, format!("{:?}", dot)).expect("Unable to write file");
-
-        std::process::Command::new("sed")
-            .args(&["-i", "s/'//g", dot_file_path_str])
-            .output()
-            .expect("Failed to execute sed command");
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        let dot_file = dir.join(filename);
+        fs::write(&dot_file, dot)
+            .map_err(|err| format!("failed to write {}: {}", dot_file.display(), err))?;
+        Ok(dot_file)
+    }
+}
 
-        let png_file_path = path.join(filename.replace(".dot", ".png"));
-        let png_file_path_str = png_file_path.to_str().unwrap();
-        std::process::Command::new("dot")
-            .args(&["-Tpng", dot_file_path_str, "-o", png_file_path_str])
-            .output()
-            .expect("Failed to execute dot command");
+/// 调用 Graphviz 把 DOT 文件渲染为同名的 PNG 图片，返回图片的路径
+///
+/// 没有安装 Graphviz 或渲染失败时返回错误信息，不会 panic
+pub fn render_dot(dot_file: &Path) -> Result<PathBuf, String> {
+    let output = dot_file.with_extension("png");
+    let result = std::process::Command::new("dot")
+        .arg("-Tpng")
+        .arg(dot_file)
+        .arg("-o")
+        .arg(&output)
+        .output();
+    match result {
+        Ok(result) if result.status.success() => Ok(output),
+        Ok(result) => Err(format!(
+            "dot failed to render {}: {}",
+            dot_file.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(format!(
+            "Graphviz `dot` was not found, install Graphviz to render {}",
+            dot_file.display()
+        )),
+        Err(err) => Err(format!("failed to run dot: {}", err)),
     }
 }
@@ -1,16 +1,36 @@
+#![allow(clippy::upper_case_acronyms)]
 #[macro_use]
 extern crate lazy_static;
 use dfa::DFA;
+use graph::render_dot;
 use nfa::NFA;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 mod dfa;
 mod graph;
 mod nfa;
+mod regex;
 
 lazy_static! {
     static ref TEST_ID: Mutex<usize> = Mutex::new(0);
 }
 
+/// 测试输出的 DOT 文件所在的目录
+const PICTURE_DIR: &str = "result_pic";
+
+/// 打印写出 DOT 文件时的错误，使用 `cargo run -- --render` 时再渲染为图片
+fn save_picture(dot_file: Result<PathBuf, String>) {
+    let render = std::env::args().skip(1).any(|arg| arg == "--render");
+    match dot_file {
+        Ok(dot_file) if render => match render_dot(&dot_file) {
+            Ok(png) => println!("rendered {}", png.display()),
+            Err(err) => println!("{}", err),
+        },
+        Ok(_) => {}
+        Err(err) => println!("{}", err),
+    }
+}
+
 struct Test {
     nfa: NFA,
     dfa: DFA,
@@ -19,10 +39,12 @@ struct Test {
 }
 
 impl Test {
-    pub fn from(regular_expression: String, expression: Vec<&str>) -> Self {
-        let expression = expression.iter().map(|x| x.to_string()).collect();
+    /// 测试串由 `DFA::examples` 生成：最短的 3 个被接受的串和 3 个不被接受的串
+    pub fn from(regular_expression: String) -> Self {
         let nfa = NFA::from(&regular_expression);
         let dfa = DFA::from(&nfa);
+        let (accepted, rejected) = dfa.examples(3);
+        let expression = accepted.into_iter().chain(rejected).collect();
         Test {
             nfa,
             dfa,
@@ -37,10 +59,13 @@ impl Test {
             TEST_ID.lock().unwrap()
         );
         println!("regular expression: {}\n", self.regular_expression);
-        self.nfa.show(*TEST_ID.lock().unwrap());
+        save_picture(
+            self.nfa
+                .show(Path::new(PICTURE_DIR), *TEST_ID.lock().unwrap()),
+        );
 
         for exp in &self.expression {
-            println!("expression: {}", exp);
+            println!("expression: {:?}", exp);
             let identified = self.nfa.contains(exp);
             println!("identified: {}\n", identified);
         }
@@ -50,10 +75,13 @@ impl Test {
             TEST_ID.lock().unwrap()
         );
         println!("regular expression: {}\n", self.regular_expression);
-        self.dfa.show(*TEST_ID.lock().unwrap());
+        save_picture(
+            self.dfa
+                .show(Path::new(PICTURE_DIR), *TEST_ID.lock().unwrap()),
+        );
 
         for exp in &self.expression {
-            println!("expression: {}", exp);
+            println!("expression: {:?}", exp);
             let identified = self.dfa.contains(exp);
             println!("identified: {}\n", identified);
         }
@@ -64,42 +92,50 @@ impl Test {
 
 fn test1() {
     let regular_expression = "(a(ab|c))*d*";
-    let expression = vec![
-        "aabacacaabddd",
-        "aabacacabdd",
-        "ddddddd",
-        "aaaaaaaaaa",
-        "",
-        "hello world",
-    ];
-
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
     test.run();
 }
 
 fn test2() {
     let regular_expression = "woc*";
-    let expression = vec![
-        "wocccccc",
-        "woc",
-        "wo",
-        "woccc",
-        "wocccccc",
-        "woccccccwocccccc",
-    ];
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
     test.run();
 }
 
 fn test3() {
     let regular_expression = "((ab)*|aaa)";
-    let expression = vec!["", "aaa", "ab", "abababa", "abababab"];
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+}
+
+fn test4() {
+    let regular_expression = r"-?[0-9]+(\.[0-9]+)?";
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+}
+
+fn test5() {
+    let regular_expression = r"[a-zA-Z_]\w*|\*+|[^a-z ]?";
+    let test = Test::from(regular_expression.to_string());
     test.run();
 }
 
+fn test6() {
+    for regular_expression in [
+        "a|", "(ab", "ab)", "*a", "a(|b)", "()", "[z-a]", r"[a-\d]", "[ab", "a\\", "aεb", "[αε]",
+    ] {
+        match NFA::try_from(regular_expression) {
+            Ok(_) => println!("regular expression: {} is valid\n", regular_expression),
+            Err(err) => println!("{}\n", err),
+        }
+    }
+}
+
 fn main() {
     test1();
     test2();
     test3();
+    test4();
+    test5();
+    test6();
 }
@@ -1,7 +1,8 @@
 use crate::graph::Graph_;
-use core::panic;
+use crate::regex::{add_connect, tokenize, RegexError, Token};
 use std::fmt::Debug;
 use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
 
 pub struct NFA {
     /// 确保起点为0，终点为 len - 1
@@ -14,158 +15,132 @@ impl Debug for NFA {
     }
 }
 
-impl NFA {
-    pub fn from(exp: &str) -> NFA {
-        let transfer_exp = add_connect(exp);
-        // println!("transferd expression is {}", transfer_exp);
+impl TryFrom<&str> for NFA {
+    type Error = RegexError;
+
+    fn try_from(exp: &str) -> Result<Self, Self::Error> {
+        let tokens = add_connect(tokenize(exp)?);
         let mut nfa_stack = Vec::new();
-        let mut op_stack = Vec::new();
-        // ( ) > * > + > |
-        for c in transfer_exp.chars() {
-            match c {
-                '(' => {
-                    op_stack.push(c);
-                }
-                ')' => {
-                    while let Some(op) = op_stack.pop() {
-                        if op == '(' {
-                            break;
-                        }
-                        let b = nfa_stack.pop().unwrap();
-                        let a = nfa_stack.pop().unwrap();
-                        match op {
-                            '*' => {
-                                nfa_stack.push(repeat(&a));
-                            }
-                            '|' => {
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '+' => {
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
-                        }
-                    }
-                }
-                '*' => {
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(repeat(&a));
+        let mut op_stack: Vec<(usize, Token)> = Vec::new();
+        // 下一个词法单元是否必须是操作数（字符或 `(`），用于发现 `*a`、`a|`、`()` 这类缺少操作数的写法
+        let mut expect_operand = true;
+        // ( ) > * + ? > Concat > |
+        for (offset, token) in tokens {
+            let is_operand = matches!(token, Token::Chars(_) | Token::LParen);
+            if expect_operand && !is_operand {
+                return Err(missing_operand(exp, offset));
+            }
+            expect_operand = matches!(token, Token::LParen | Token::Alt | Token::Concat);
+            match token {
+                Token::LParen => {
+                    op_stack.push((offset, token));
                 }
-                '+' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
+                Token::RParen => loop {
+                    match op_stack.pop() {
+                        Some((_, Token::LParen)) => break,
+                        Some((op_offset, op)) => apply(exp, op_offset, &op, &mut nfa_stack)?,
+                        None => {
+                            return Err(RegexError::new(
+                                exp,
+                                offset,
+                                "unmatched `)`",
+                                "an operator",
+                            ))
                         }
                     }
-                    op_stack.push(c);
+                },
+                Token::Star | Token::Plus | Token::Optional => {
+                    let a = nfa_stack
+                        .pop()
+                        .ok_or_else(|| missing_operand(exp, offset))?;
+                    nfa_stack.push(match token {
+                        Token::Star => repeat(&a),
+                        Token::Plus => and(&a, &repeat(&a)),
+                        _ => or(&a, &empty()),
+                    });
                 }
-                '|' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
+                Token::Concat | Token::Alt => {
+                    while let Some((op_offset, op)) = op_stack.pop() {
+                        if precedence(&op) < precedence(&token) {
+                            op_stack.push((op_offset, op));
+                            break;
                         }
+                        apply(exp, op_offset, &op, &mut nfa_stack)?;
                     }
-                    op_stack.push(c);
+                    op_stack.push((offset, token));
                 }
-                _ => {
-                    assert!(c.is_alphanumeric());
-                    nfa_stack.push(teminate(c));
+                Token::Chars(chars) => {
+                    nfa_stack.push(class(&chars));
                 }
             }
-            // println!("============ c is: {} ===============", c);
-            // println!("nfa_stack: {:?}", nfa_stack);
-            // println!("op_stack: {:?}", op_stack);
-            // println!("");
+        }
+        if expect_operand {
+            return Err(missing_operand(exp, exp.len()));
         }
 
-        while let Some(op) = op_stack.pop() {
-            match op {
-                '*' => {
-                    panic!("error: * can't be here");
-                }
-                '+' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(and(&a, &b));
-                }
-                '|' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(or(&a, &b));
-                }
-                '(' => {
-                    panic!("error: ( can't be here");
-                }
-                ')' => {
-                    panic!("error: ) can't be here");
-                }
-                _ => {
-                    panic!("error: unknown operator {}", op);
-                }
+        while let Some((offset, op)) = op_stack.pop() {
+            if op == Token::LParen {
+                return Err(RegexError::new(
+                    exp,
+                    offset,
+                    "unclosed `(`",
+                    "a matching `)`",
+                ));
             }
+            apply(exp, offset, &op, &mut nfa_stack)?;
         }
 
-        assert_eq!(nfa_stack.len(), 1);
-        assert_eq!(op_stack.len(), 0);
-        nfa_stack.pop().unwrap()
+        match (nfa_stack.pop(), nfa_stack.is_empty()) {
+            (Some(nfa), true) => Ok(nfa),
+            _ => Err(missing_operand(exp, exp.len())),
+        }
+    }
+}
+
+impl NFA {
+    /// 表达式不合法时 panic 并打印错误位置，需要处理错误时使用 `NFA::try_from`
+    pub fn from(exp: &str) -> NFA {
+        NFA::try_from(exp).unwrap_or_else(|err| panic!("{}", err))
     }
 
-    pub fn show(&self, id: usize) {
-        self.graph.show_graph(&format!("nfa_{}.dot", id));
+    pub fn show(&self, dir: &Path, id: usize) -> Result<PathBuf, String> {
+        self.graph.show_graph(dir, &format!("nfa_{}.dot", id))
     }
 
     pub fn contains(&self, exp: &str) -> bool {
-        let mut succeed = false;
-        self.graph
-            .dfs_search(0, &vec![self.graph.edges.len() - 1], 0, exp, &mut succeed)
+        self.graph.simulate(0, &[self.graph.edges.len() - 1], exp)
     }
 }
 
-fn offset(edge: &Vec<(usize, char)>, offset: usize) -> Vec<(usize, char)> {
-    let mut result = Vec::new();
-    for i in 0..edge.len() {
-        result.push((edge[i].0 + offset, edge[i].1));
+/// 二元运算符的优先级，`(` 只会被 `)` 弹出
+fn precedence(op: &Token) -> usize {
+    match op {
+        Token::Alt => 1,
+        Token::Concat => 2,
+        _ => 0,
     }
-    result
+}
+
+fn missing_operand(exp: &str, offset: usize) -> RegexError {
+    RegexError::new(exp, offset, "missing operand", "a character or `(`")
+}
+
+/// pop two operands and apply the binary operator
+fn apply(exp: &str, offset: usize, op: &Token, nfa_stack: &mut Vec<NFA>) -> Result<(), RegexError> {
+    let (Some(b), Some(a)) = (nfa_stack.pop(), nfa_stack.pop()) else {
+        return Err(missing_operand(exp, offset));
+    };
+    nfa_stack.push(match op {
+        Token::Alt => or(&a, &b),
+        _ => and(&a, &b),
+    });
+    Ok(())
+}
+
+fn offset(edge: &[(usize, char)], offset: usize) -> Vec<(usize, char)> {
+    edge.iter()
+        .map(|&(to, label)| (to + offset, label))
+        .collect()
 }
 
 /// connect a and b
@@ -174,12 +149,8 @@ fn and(a: &NFA, b: &NFA) -> NFA {
     let b_edges = b.graph.edges.clone();
     let mut result = a_edges.clone();
 
-    for i in 0..b_edges.len() {
-        let mut tmp = Vec::new();
-        for j in 0..b_edges[i].len() {
-            tmp.push((b_edges[i][j].0 + a_edges.len(), b_edges[i][j].1));
-        }
-        result.push(tmp);
+    for edge in &b_edges {
+        result.push(offset(edge, a_edges.len()));
     }
     result[a_edges.len() - 1].push((a_edges.len(), 'ε'));
     NFA {
@@ -245,30 +216,27 @@ fn repeat(a: &NFA) -> NFA {
 
 /// teminate
 fn teminate(a: char) -> NFA {
-    let mut result = Vec::new();
-    result.push(vec![(1, a)]);
-    result.push(vec![]);
     NFA {
-        graph: Graph_ { edges: result },
+        graph: Graph_ {
+            edges: vec![vec![(1, a)], vec![]],
+        },
     }
 }
 
-/// transfer the raw expression
-fn add_connect(exp: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = exp.chars().collect();
-    let len = chars.len();
-    for i in 0..len {
-        result.push(chars[i]);
-        if i + 1 < len {
-            let current = chars[i];
-            let next = chars[i + 1];
-            if (current.is_alphanumeric() || current == ')' || current == '*')
-                && (next.is_alphanumeric() || next == '(')
-            {
-                result.push('+');
-            }
-        }
+/// match any char of the class
+fn class(chars: &[char]) -> NFA {
+    if let [c] = chars {
+        return teminate(*c);
+    }
+    let edges = chars.iter().map(|&c| (1, c)).collect();
+    NFA {
+        graph: Graph_ {
+            edges: vec![edges, vec![]],
+        },
     }
-    result
+}
+
+/// match the empty string
+fn empty() -> NFA {
+    teminate('ε')
 }
@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+
+/// 闭区间 `[start, end]` 内的所有字符，作为自动机边上的标签
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CharRange {
+    pub start: char,
+    pub end: char,
+}
+
+impl CharRange {
+    pub fn new(start: char, end: char) -> Self {
+        assert!(start <= end, "error: invalid range {:?}-{:?}", start, end);
+        CharRange { start, end }
+    }
+
+    pub fn single(c: char) -> Self {
+        CharRange { start: c, end: c }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.start <= c && c <= self.end
+    }
+
+    /// 区间中用于展示的一个字符，优先取可见的 ASCII 字符
+    pub fn representative(&self) -> char {
+        ('!'..='~')
+            .find(|&c| self.contains(c))
+            .unwrap_or(self.start)
+    }
+
+    /// 区间内字符的个数
+    pub fn len(&self) -> usize {
+        let len = self.end as usize - self.start as usize + 1;
+        // 区间跨过代理区时，代理区中的码位不是字符
+        if self.start <= '\u{D7FF}' && self.end >= '\u{E000}' {
+            len - 0x800
+        } else {
+            len
+        }
+    }
+
+    /// 区间内从 0 开始的第 n 个字符，跳过代理区
+    pub fn nth(&self, n: usize) -> Option<char> {
+        if n >= self.len() {
+            return None;
+        }
+        let code = self.start as u32 + n as u32;
+        if self.start <= '\u{D7FF}' && code > 0xD7FF {
+            char::from_u32(code + 0x800)
+        } else {
+            char::from_u32(code)
+        }
+    }
+}
+
+impl Display for CharRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", escape_char(self.start))
+        } else {
+            write!(f, "{}-{}", escape_char(self.start), escape_char(self.end))
+        }
+    }
+}
+
+/// 用于展示的字符，不可见字符按 Rust 的规则转义
+///
+/// 字符 `ε` 写为 `\u{3b5}`，与 ε 转移的标签 `ε` 区分
+pub fn escape_char(c: char) -> String {
+    match c {
+        'ε' => "\\u{3b5}".to_string(),
+        _ => c.escape_debug().to_string(),
+    }
+}
+
+/// 下一个字符，跳过代理区
+pub fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// 上一个字符，跳过代理区
+pub fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        _ => char::from_u32(c as u32 - 1),
+    }
+}
+
+/// 所有 Unicode 字符
+pub fn universe() -> Vec<CharRange> {
+    vec![CharRange::new('\0', char::MAX)]
+}
+
+/// 排序并合并重叠或相邻的区间
+pub fn normalize(mut ranges: Vec<CharRange>) -> Vec<CharRange> {
+    ranges.sort();
+    let mut result: Vec<CharRange> = Vec::new();
+    for range in ranges {
+        if let Some(last) = result.last_mut() {
+            if next_char(last.end).is_none_or(|next| range.start <= next) {
+                last.end = last.end.max(range.end);
+                continue;
+            }
+        }
+        result.push(range);
+    }
+    result
+}
+
+/// 相对于所有 Unicode 字符的补集，`ranges` 需要已经规范化
+pub fn complement(ranges: &[CharRange]) -> Vec<CharRange> {
+    let mut result = Vec::new();
+    let mut start = Some('\0');
+    for range in ranges {
+        if let Some(s) = start {
+            if s < range.start {
+                result.push(CharRange::new(s, prev_char(range.start).unwrap()));
+            }
+        }
+        start = next_char(range.end);
+    }
+    if let Some(s) = start {
+        result.push(CharRange::new(s, char::MAX));
+    }
+    result
+}
+
+/// 把若干个可能重叠的区间划分为互不相交的等价类
+///
+/// 每个等价类要么完全包含于某个输入区间，要么与之不相交，
+/// 因此同一等价类中的字符在自动机上的转移完全相同
+pub fn partition(ranges: &[CharRange]) -> Vec<CharRange> {
+    // 所有区间的边界：起点以及终点的下一个字符
+    let mut bounds = Vec::new();
+    for range in ranges {
+        bounds.push(range.start);
+        if let Some(next) = next_char(range.end) {
+            bounds.push(next);
+        }
+    }
+    bounds.sort();
+    bounds.dedup();
+
+    let covered = normalize(ranges.to_vec());
+    let mut result = Vec::new();
+    for (i, &start) in bounds.iter().enumerate() {
+        let end = match bounds.get(i + 1) {
+            Some(&next) => prev_char(next).unwrap(),
+            None => char::MAX,
+        };
+        if covered.iter().any(|range| range.contains(start)) {
+            result.push(CharRange::new(start, end));
+        }
+    }
+    result
+}
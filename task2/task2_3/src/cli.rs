@@ -0,0 +1,172 @@
+use crate::dfa::DFA;
+use crate::nfa::NFA;
+use crate::serialize::Automaton;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: automata <command> [options] <arguments>
+
+commands:
+    regex2nfa <regex>              print the Thompson NFA
+    nfa2dfa <regex>                print the DFA built by subset construction
+    minimize <regex>               print the minimized DFA
+    match <regex> <input>...       check whether each input is accepted
+    equiv <regex> <regex>          check whether two regexes are equivalent
+    dot <regex>                    print the DOT source of the NFA
+    test [--render]                run the built-in tests, `--render` also renders the pictures
+
+options:
+    --json                         print automata as JSON instead of transition tables
+    --dfa, --min                   with `dot`, print the DFA or the minimized DFA
+
+options go between the command and its first argument; the arguments, and
+anything after `--`, are never treated as options
+
+an argument `@path` is read from the file at path; for `match`, each line of
+the file is a separate input
+
+exit status: 0 on success, accepted or equivalent; 1 when rejected or not
+equivalent; 2 on invalid usage or an invalid regex";
+
+/// 命令行的入口，args 不包含程序名
+pub fn run(args: &[String]) -> ExitCode {
+    match execute(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// 执行一条命令，返回是否接受或等价，参数或表达式有误时返回错误信息
+fn execute(args: &[String]) -> Result<bool, String> {
+    let (command, mut rest) = args.split_first().ok_or_else(|| USAGE.to_string())?;
+    // 选项只能写在命令和参数之间，`--` 之后的参数即使以 `--` 开头也不作为选项
+    let (mut json, mut dot_dfa, mut dot_min) = (false, false, false);
+    while let Some((arg, tail)) = rest.split_first() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--dfa" => dot_dfa = true,
+            "--min" => dot_min = true,
+            "--" => {
+                rest = tail;
+                break;
+            }
+            option if option.starts_with("--") => {
+                return Err(format!("unknown option `{}`\n\n{}", option, USAGE));
+            }
+            _ => break,
+        }
+        rest = tail;
+    }
+    // 默认输出转移表，`--json` 时输出可以重新加载的 JSON
+    let print = |table: String, automaton: Automaton| {
+        if json {
+            println!("{}", automaton.to_json());
+        } else {
+            print!("{}", table);
+        }
+    };
+
+    match (command.as_str(), rest) {
+        ("regex2nfa", [regex]) => {
+            let nfa = parse(regex)?;
+            print(nfa.to_string(), nfa.to_automaton());
+        }
+        ("nfa2dfa", [regex]) => {
+            let dfa = DFA::from(&parse(regex)?);
+            print(dfa.to_string(), dfa.to_automaton());
+        }
+        ("minimize", [regex]) => {
+            let dfa = minimized(regex)?;
+            print(dfa.to_string(), dfa.to_automaton());
+        }
+        ("match", [regex, inputs @ ..]) if !inputs.is_empty() => {
+            let dfa = minimized(regex)?;
+            let mut all = true;
+            let mut lines = Vec::new();
+            for input in inputs.iter() {
+                // 从文件读入的参数中，每一行是一个输入
+                match input.strip_prefix('@') {
+                    Some(path) => lines.extend(read_file(path)?.lines().map(str::to_string)),
+                    None => lines.push(input.to_string()),
+                }
+            }
+            for input in &lines {
+                let accepted = dfa.contains(input);
+                println!("{:?}: {}", input, accepted);
+                all &= accepted;
+            }
+            return Ok(all);
+        }
+        ("equiv", [a, b]) => {
+            return match minimized(a)?.equivalent(&minimized(b)?) {
+                Ok(()) => {
+                    println!("equivalent");
+                    Ok(true)
+                }
+                Err(err) => {
+                    println!("not equivalent: {}", err);
+                    Ok(false)
+                }
+            };
+        }
+        ("dot", [regex]) => {
+            let nfa = parse(regex)?;
+            let dot = if dot_dfa || dot_min {
+                let mut dfa = DFA::from(&nfa);
+                if dot_min {
+                    dfa.minimize();
+                }
+                dfa.graph.to_dot(dfa.start_state, &dfa.end_states)
+            } else {
+                nfa.graph.to_dot(0, &[nfa.graph.edges.len() - 1])
+            };
+            print!("{}", dot);
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(true)
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|err| format!("error: cannot read {}: {}", path, err))
+}
+
+/// `@path` 形式的表达式从文件读入，并去掉末尾的换行
+fn parse(regex: &str) -> Result<NFA, String> {
+    let regex = match regex.strip_prefix('@') {
+        Some(path) => read_file(path)?.trim_end_matches(['\n', '\r']).to_string(),
+        None => regex.to_string(),
+    };
+    NFA::try_from(regex.as_str()).map_err(|err| err.to_string())
+}
+
+fn minimized(regex: &str) -> Result<DFA, String> {
+    let mut dfa = DFA::from(&parse(regex)?);
+    dfa.minimize();
+    Ok(dfa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_only_come_before_the_arguments() {
+        assert_eq!(execute(&args(&["match", "--json", "a", "a"])), Ok(true));
+        // 参数中的 `--json` 是要匹配的输入
+        assert_eq!(execute(&args(&["match", "a", "--json"])), Ok(false));
+        assert_eq!(
+            execute(&args(&["match", "--", "--json", "--json"])),
+            Ok(true)
+        );
+        let err = execute(&args(&["match", "--jsn", "a", "a"])).unwrap_err();
+        assert!(err.starts_with("unknown option `--jsn`"));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::charset::{normalize, CharRange};
+use crate::dfa::DFA;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// 把DFA生成为独立的 Rust 源文件，类似 flex/re2c 的输出
+///
+/// 生成的代码只依赖标准库，包含词法单元名称表、接受状态表、
+/// 基于 `match` 的转移函数以及按最长匹配读取一个词法单元的 `next_token`；
+/// `token_names[i]` 为第 i 个模式的名称
+pub fn generate_rust(dfa: &DFA, token_names: &[String]) -> String {
+    let states = dfa.graph.edges.len();
+    let mut code = String::new();
+    writeln!(code, "// Generated from a minimized DFA, do not edit.").unwrap();
+    writeln!(code, "// states: {}, tokens: {}", states, token_names.len()).unwrap();
+    writeln!(code).unwrap();
+
+    writeln!(
+        code,
+        "pub const TOKEN_NAMES: [&str; {}] = {:?};",
+        token_names.len(),
+        token_names
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "const START: usize = {};", dfa.start_state).unwrap();
+    writeln!(code).unwrap();
+
+    let accept: Vec<String> = (0..states)
+        .map(|state| match dfa.accept_tags.get(&state) {
+            Some(tag) => format!("Some({})", tag),
+            None => "None".to_string(),
+        })
+        .collect();
+    writeln!(code, "/// 接受状态对应的词法单元编号").unwrap();
+    writeln!(
+        code,
+        "const ACCEPT: [Option<usize>; {}] = [{}];",
+        states,
+        accept.join(", ")
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+
+    writeln!(
+        code,
+        "fn transition(state: usize, c: char) -> Option<usize> {{"
+    )
+    .unwrap();
+    writeln!(code, "    match (state, c) {{").unwrap();
+    for (from, edges) in dfa.graph.edges.iter().enumerate() {
+        // 按目标状态分组，连续的字符合并为区间
+        let mut targets: BTreeMap<usize, Vec<CharRange>> = BTreeMap::new();
+        for &(to, label) in edges {
+            targets.entry(to).or_default().extend(label.range());
+        }
+        for (to, ranges) in targets {
+            writeln!(
+                code,
+                "        ({}, {}) => Some({}),",
+                from,
+                char_pattern(&normalize(ranges)),
+                to
+            )
+            .unwrap();
+        }
+    }
+    writeln!(code, "        _ => None,").unwrap();
+    writeln!(code, "    }}").unwrap();
+    writeln!(code, "}}").unwrap();
+    writeln!(code).unwrap();
+
+    code.push_str(NEXT_TOKEN);
+    code
+}
+
+/// 把规范化的区间写成 `match` 的模式，例如 `'0'..='9' | '_'`
+fn char_pattern(ranges: &[CharRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start == range.end {
+                format!("{:?}", range.start)
+            } else {
+                format!("{:?}..={:?}", range.start, range.end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+const NEXT_TOKEN: &str = r#"/// 从 text 的第 start 个字节开始按最长匹配读取一个词法单元，返回其名称和结束位置
+pub fn next_token(text: &str, start: usize) -> Option<(&'static str, usize)> {
+    let mut state = START;
+    let mut last_accept = ACCEPT[state].map(|tag| (tag, start));
+    for (offset, c) in text[start..].char_indices() {
+        match transition(state, c) {
+            Some(next) => state = next,
+            None => break,
+        }
+        if let Some(tag) = ACCEPT[state] {
+            last_accept = Some((tag, start + offset + c.len_utf8()));
+        }
+    }
+    last_accept
+        .filter(|&(_, end)| end > start)
+        .map(|(tag, end)| (TOKEN_NAMES[tag], end))
+}
+"#;
@@ -0,0 +1,154 @@
+use crate::charset::{normalize, CharRange};
+use crate::regex::Regex;
+
+/// 不匹配任何串的表达式，用空字符类表示
+pub fn null() -> Regex {
+    Regex::Class(Vec::new())
+}
+
+fn is_null(regex: &Regex) -> bool {
+    matches!(regex, Regex::Class(set) if set.is_empty())
+}
+
+/// 把语法树转换为只含 `Empty`、`Class`、`Concat`、`Alt`、`Star` 的规范形式
+///
+/// 捕获组被忽略，`a+`、`a?`、`a{m,n}` 展开为连接、或和闭包
+pub fn normal_form(regex: &Regex) -> Regex {
+    match regex {
+        Regex::Empty => Regex::Empty,
+        Regex::Char(c) => Regex::Class(vec![CharRange::single(*c)]),
+        Regex::Class(set) => Regex::Class(set.clone()),
+        Regex::Concat(items) => items.iter().map(normal_form).fold(Regex::Empty, concat),
+        Regex::Alt(items) => alt(items.iter().map(normal_form).collect()),
+        Regex::Star(a) => star(normal_form(a)),
+        Regex::Plus(a) => {
+            let a = normal_form(a);
+            concat(a.clone(), star(a))
+        }
+        Regex::Opt(a) => alt(vec![normal_form(a), Regex::Empty]),
+        Regex::Repeat(a, min, max) => {
+            let a = normal_form(a);
+            let mut result = Regex::Empty;
+            for _ in 0..*min {
+                result = concat(result, a.clone());
+            }
+            match max {
+                Some(max) => {
+                    for _ in *min..*max {
+                        result = concat(result, alt(vec![a.clone(), Regex::Empty]));
+                    }
+                }
+                None => result = concat(result, star(a)),
+            }
+            result
+        }
+        Regex::Group(_, a) => normal_form(a),
+    }
+}
+
+/// 连接，∅ 为零元，ε 为单位元，嵌套的连接展开为一层
+fn concat(a: Regex, b: Regex) -> Regex {
+    if is_null(&a) || is_null(&b) {
+        return null();
+    }
+    let mut items = Vec::new();
+    for item in [a, b] {
+        match item {
+            Regex::Empty => {}
+            Regex::Concat(inner) => items.extend(inner),
+            item => items.push(item),
+        }
+    }
+    match items.len() {
+        0 => Regex::Empty,
+        1 => items.pop().unwrap(),
+        _ => Regex::Concat(items),
+    }
+}
+
+/// 或，展开嵌套、去掉 ∅、合并字符类，并把分支排序去重，保证相同的语言有相同的形式
+fn alt(items: Vec<Regex>) -> Regex {
+    let mut result = Vec::new();
+    let mut class = Vec::new();
+    let mut stack = items;
+    while let Some(item) = stack.pop() {
+        match item {
+            Regex::Alt(inner) => stack.extend(inner),
+            Regex::Class(set) => class.extend(set),
+            item => result.push(item),
+        }
+    }
+    if !class.is_empty() {
+        result.push(Regex::Class(normalize(class)));
+    }
+    result.sort();
+    result.dedup();
+    match result.len() {
+        0 => null(),
+        1 => result.pop().unwrap(),
+        _ => Regex::Alt(result),
+    }
+}
+
+/// 闭包，`(a*)*` → `a*`，`ε*` 和 `∅*` → `ε`
+fn star(a: Regex) -> Regex {
+    match a {
+        Regex::Empty => Regex::Empty,
+        a if is_null(&a) => Regex::Empty,
+        Regex::Star(a) => Regex::Star(a),
+        a => Regex::Star(Box::new(a)),
+    }
+}
+
+/// 是否匹配空串，regex 需要是规范形式
+pub fn nullable(regex: &Regex) -> bool {
+    match regex {
+        Regex::Empty | Regex::Star(_) => true,
+        Regex::Concat(items) => items.iter().all(nullable),
+        Regex::Alt(items) => items.iter().any(nullable),
+        _ => false,
+    }
+}
+
+/// Brzozowski 导数 ∂c(r)：r 所匹配的串中以 c 开头的那些去掉 c 之后的集合，regex 需要是规范形式
+pub fn derivative(regex: &Regex, c: char) -> Regex {
+    match regex {
+        Regex::Class(set) if set.iter().any(|range| range.contains(c)) => Regex::Empty,
+        Regex::Concat(items) => {
+            let rest = items[1..]
+                .iter()
+                .fold(Regex::Empty, |result, item| concat(result, item.clone()));
+            let head = concat(derivative(&items[0], c), rest.clone());
+            if nullable(&items[0]) {
+                alt(vec![head, derivative(&rest, c)])
+            } else {
+                head
+            }
+        }
+        Regex::Alt(items) => alt(items.iter().map(|item| derivative(item, c)).collect()),
+        Regex::Star(a) => concat(derivative(a, c), regex.clone()),
+        _ => null(),
+    }
+}
+
+/// 规范形式中出现的所有字符区间，导数中不会出现新的区间
+pub fn ranges(regex: &Regex) -> Vec<CharRange> {
+    match regex {
+        Regex::Class(set) => set.clone(),
+        Regex::Concat(items) | Regex::Alt(items) => items.iter().flat_map(ranges).collect(),
+        Regex::Star(a) => ranges(a),
+        _ => Vec::new(),
+    }
+}
+
+/// 不构造自动机，依次对每个字符求导数，最后判断是否匹配空串
+pub fn is_match(regex: &Regex, text: &str) -> bool {
+    let mut regex = normal_form(regex);
+    for c in text.chars() {
+        regex = derivative(&regex, c);
+        if is_null(&regex) {
+            return false;
+        }
+    }
+    nullable(&regex)
+}
@@ -1,104 +1,741 @@
-use crate::{dsu::DSU, graph::Graph_, nfa::NFA};
-use std::collections::{HashMap, HashSet};
+use crate::{
+    charset::{complement, next_char, normalize, partition, universe, CharRange},
+    derivative::{derivative, normal_form, null, nullable, ranges},
+    dsu::DSU,
+    graph::{Graph_, Label},
+    nfa::NFA,
+    regex::Regex,
+};
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 
+#[derive(Clone)]
 pub struct DFA {
-    start_state: usize,
-    end_states: Vec<usize>,
-    graph: Graph_,
+    pub start_state: usize,
+    pub end_states: Vec<usize>,
+    /// 接受状态对应的模式编号，同时接受多个模式时取编号最小（优先级最高）的
+    pub accept_tags: HashMap<usize, usize>,
+    pub graph: Graph_,
+    /// 子集构造得到的DFA中每个状态对应的NFA状态集合，其他方式得到的DFA为空
+    pub nfa_states: Vec<Vec<usize>>,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, PartialOrd, Ord)]
-pub enum StateType {
-    Start,
-    End,
-    Normal,
+/// 状态转移表，`→` 标记开始状态，`*` 标记接受状态，子集构造得到的DFA同时列出NFA状态集合
+impl Display for DFA {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.graph
+                .transition_table(self.start_state, &self.end_states, &self.nfa_states)
+        )
+    }
 }
 
 impl DFA {
     pub fn from(nfa: &NFA) -> Self {
+        DFA::from_accepts(nfa, &[nfa.graph.edges.len() - 1])
+    }
+
+    /// 子集构造，`accepts[i]` 为第 i 个模式在NFA中的接受状态，编号越小优先级越高
+    ///
+    /// 使用工作队列按广度优先的顺序生成状态集合，开始状态编号为 0，
+    /// 每次运行得到的状态编号都相同
+    pub fn from_accepts(nfa: &NFA, accepts: &[usize]) -> Self {
         let graph = &nfa.graph;
         let terminal = graph.get_terminal();
         let start_state = graph.extend_from_point(0);
         let mut end_states = Vec::new();
-        let mut states = HashSet::new();
-        states.insert(start_state.clone());
-        graph.dfs_get_dfa_states(&start_state, &terminal, &mut states);
-        let states = states.into_iter().collect::<Vec<_>>();
-        let mut index_of_state = HashMap::new();
-        for (i, state) in states.iter().enumerate() {
-            index_of_state.insert(state, i);
-        }
-        let mut graph_res = Graph_::new(states.len());
+        let mut accept_tags = HashMap::new();
+        let mut index_of_state = HashMap::from([(start_state.clone(), 0)]);
+        let mut states = vec![start_state];
+        let mut graph_res = Graph_::new(0);
 
-        for u in states.iter() {
+        let mut i = 0;
+        while i < states.len() {
+            graph_res.edges.push(Vec::new());
             for &w in &terminal {
-                let v = graph.extend_from_point_vec_and_val(u, w);
-                if v.len() != 0 {
-                    graph_res.add_edge(
-                        *index_of_state.get(u).unwrap(),
-                        *index_of_state.get(&v).unwrap(),
-                        w,
-                    );
+                // 同一区间内的字符转移相同，取区间的第一个字符作为代表
+                let v = graph.extend_from_point_vec_and_val(&states[i], w.start);
+                if v.is_empty() {
+                    continue;
                 }
+                let to = *index_of_state.entry(v.clone()).or_insert_with(|| {
+                    states.push(v);
+                    states.len() - 1
+                });
+                graph_res.add_edge(i, to, Label::from(w));
             }
-            if u.contains(&(nfa.graph.edges.len() - 1)) {
-                end_states.push(*index_of_state.get(u).unwrap());
+            if let Some(tag) = accepts.iter().position(|accept| states[i].contains(accept)) {
+                end_states.push(i);
+                accept_tags.insert(i, tag);
             }
+            i += 1;
         }
 
+        graph_res.merge_ranges();
         DFA {
-            start_state: *index_of_state.get(&start_state).unwrap(),
-            end_states: end_states,
+            start_state: 0,
+            end_states,
+            accept_tags,
             graph: graph_res,
+            nfa_states: states,
         }
     }
 
-    fn type_of(&self, state: usize) -> StateType {
-        if state == self.start_state {
-            StateType::Start
-        } else if self.end_states.contains(&state) {
-            StateType::End
-        } else {
-            StateType::Normal
+    /// 不经过NFA，用 Brzozowski 导数直接构造DFA
+    ///
+    /// 每个状态是一个规范形式的表达式，读入 c 后到达它对 c 的导数，匹配空串的状态为接受状态；
+    /// 同一个区间等价类中的字符导数相同，不匹配任何串的导数不生成状态
+    pub fn from_derivatives(regex: &Regex) -> Self {
+        let start = normal_form(regex);
+        let terminal = partition(&ranges(&start));
+        let mut index_of_state = HashMap::from([(start.clone(), 0)]);
+        let mut states = vec![start];
+        let mut graph = Graph_::new(0);
+        let mut end_states = Vec::new();
+        let mut i = 0;
+        while i < states.len() {
+            graph.edges.push(Vec::new());
+            for &w in &terminal {
+                let next = derivative(&states[i], w.start);
+                if next == null() {
+                    continue;
+                }
+                let to = *index_of_state.entry(next.clone()).or_insert_with(|| {
+                    states.push(next);
+                    states.len() - 1
+                });
+                graph.add_edge(i, to, Label::from(w));
+            }
+            if nullable(&states[i]) {
+                end_states.push(i);
+            }
+            i += 1;
+        }
+        graph.merge_ranges();
+
+        DFA {
+            start_state: 0,
+            accept_tags: end_states.iter().map(|&state| (state, 0)).collect(),
+            end_states,
+            graph,
+            nfa_states: Vec::new(),
         }
     }
 
-    pub fn minimize(&mut self) {
+    /// 从开始状态出发按广度优先的顺序排列所有可达的状态
+    fn bfs_order(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.graph.edges.len()];
+        visited[self.start_state] = true;
+        let mut order = vec![self.start_state];
+        let mut i = 0;
+        while i < order.len() {
+            for &(v, _) in &self.graph.edges[order[i]] {
+                if !visited[v] {
+                    visited[v] = true;
+                    order.push(v);
+                }
+            }
+            i += 1;
+        }
+        order
+    }
+
+    /// 只保留 order 中的状态，并按其在 order 中的位置重新编号
+    fn retain_states(&mut self, order: &[usize]) {
         let edges = &self.graph.edges;
-        let mut map = HashMap::new();
-        let mut dsu = DSU::new(edges.len());
+        let mut index_of_state = vec![None; edges.len()];
+        for (i, &state) in order.iter().enumerate() {
+            index_of_state[state] = Some(i);
+        }
+        let mut graph = Graph_::new(order.len());
+        for (i, &state) in order.iter().enumerate() {
+            for &(to, label) in &edges[state] {
+                if let Some(to) = index_of_state[to] {
+                    graph.add_edge(i, to, label);
+                }
+            }
+        }
+
+        self.graph = graph;
+        self.start_state = index_of_state[self.start_state].unwrap();
+        self.end_states = self
+            .end_states
+            .iter()
+            .filter_map(|&state| index_of_state[state])
+            .collect();
+        self.end_states.sort();
+        if !self.nfa_states.is_empty() {
+            self.nfa_states = order
+                .iter()
+                .map(|&state| std::mem::take(&mut self.nfa_states[state]))
+                .collect();
+        }
+        self.accept_tags = self
+            .accept_tags
+            .iter()
+            .filter_map(|(&state, &tag)| index_of_state[state].map(|state| (state, tag)))
+            .collect();
+    }
+
+    /// 删除从开始状态不可达的状态和无法到达接受状态的死状态，开始状态总是保留
+    fn remove_useless_states(&mut self) {
+        let edges = &self.graph.edges;
+        let mut reverse = vec![Vec::new(); edges.len()];
+        for (u, edges) in edges.iter().enumerate() {
+            for &(v, _) in edges {
+                reverse[v].push(u);
+            }
+        }
+        let mut alive = vec![false; edges.len()];
+        let mut stack = self.end_states.clone();
+        for &state in &stack {
+            alive[state] = true;
+        }
+        while let Some(v) = stack.pop() {
+            for &u in &reverse[v] {
+                if !alive[u] {
+                    alive[u] = true;
+                    stack.push(u);
+                }
+            }
+        }
+
+        let order: Vec<usize> = self
+            .bfs_order()
+            .into_iter()
+            .filter(|&state| state == self.start_state || alive[state])
+            .collect();
+        self.retain_states(&order);
+    }
+
+    /// Hopcroft 划分求精，返回每个状态所在的等价类编号
+    ///
+    /// 初始划分按接受的模式编号区分，非接受状态为一类；字母表为所有边上区间的等价类。
+    /// 为了使转移函数完全，额外加入一个编号为 n 的陷阱状态，缺少的转移都到达陷阱状态
+    fn equivalence_classes(&self) -> Vec<usize> {
+        let n = self.graph.edges.len();
+        let terminal = self.graph.get_terminal();
+        // inverse[a][t] 为读入第 a 个区间后到达 t 的所有状态
+        let mut inverse = vec![vec![Vec::new(); n + 1]; terminal.len()];
+        for (a, range) in terminal.iter().enumerate() {
+            for u in 0..=n {
+                let v = if u < n {
+                    self.next_state(u, range.start).unwrap_or(n)
+                } else {
+                    n
+                };
+                inverse[a][v].push(u);
+            }
+        }
+
+        let mut class_of = vec![0; n + 1];
+        let mut classes: Vec<Vec<usize>> = Vec::new();
+        let mut index_of_tag = HashMap::new();
+        for (u, class) in class_of.iter_mut().enumerate() {
+            let tag = self.accept_tags.get(&u);
+            *class = *index_of_tag.entry(tag).or_insert_with(|| {
+                classes.push(Vec::new());
+                classes.len() - 1
+            });
+            classes[*class].push(u);
+        }
+
+        let mut waiting = Vec::new();
+        let mut in_waiting = HashSet::new();
+        for class in 0..classes.len() {
+            for a in 0..terminal.len() {
+                waiting.push((class, a));
+                in_waiting.insert((class, a));
+            }
+        }
+
+        while let Some((splitter, a)) = waiting.pop() {
+            in_waiting.remove(&(splitter, a));
+            // 读入第 a 个区间后进入 splitter 的状态，按所在的等价类分组
+            let mut touched: HashMap<usize, Vec<usize>> = HashMap::new();
+            for &v in &classes[splitter] {
+                for &u in &inverse[a][v] {
+                    touched.entry(class_of[u]).or_default().push(u);
+                }
+            }
+            for (class, inside) in touched {
+                if inside.len() == classes[class].len() {
+                    continue;
+                }
+                let new_class = classes.len();
+                let inside: HashSet<usize> = inside.into_iter().collect();
+                let (kept, moved): (Vec<usize>, Vec<usize>) =
+                    classes[class].iter().partition(|u| inside.contains(u));
+                for &u in &moved {
+                    class_of[u] = new_class;
+                }
+                let smaller = if kept.len() <= moved.len() {
+                    class
+                } else {
+                    new_class
+                };
+                classes[class] = kept;
+                classes.push(moved);
+                for b in 0..terminal.len() {
+                    let next = if in_waiting.contains(&(class, b)) {
+                        new_class
+                    } else {
+                        smaller
+                    };
+                    if in_waiting.insert((next, b)) {
+                        waiting.push((next, b));
+                    }
+                }
+            }
+        }
+
+        class_of.truncate(n);
+        class_of
+    }
+
+    /// 先删除不可达状态和死状态，再用 Hopcroft 算法合并等价的状态
+    ///
+    /// 接受不同模式的状态不会被合并，结果是状态数最少的DFA
+    pub fn minimize(&mut self) {
+        self.remove_useless_states();
+        // 合并后的状态不再对应单个NFA状态集合
+        self.nfa_states.clear();
+        let class_of = self.equivalence_classes();
+        let mut dsu = DSU::new(class_of.len());
+        let mut representative = HashMap::new();
+        for (u, class) in class_of.into_iter().enumerate() {
+            dsu.union(*representative.entry(class).or_insert(u), u);
+        }
+
+        self.graph.minimize(
+            &mut dsu,
+            &mut self.start_state,
+            &mut self.end_states,
+            &mut self.accept_tags,
+        );
+        self.graph.merge_ranges();
+        // 按广度优先的顺序重新编号，开始状态为 0
+        let order = self.bfs_order();
+        self.retain_states(&order);
+    }
+
+    /// 加入一个陷阱状态，使每个状态在每个字符上都有转移，原来缺少的转移都到达陷阱状态
+    ///
+    /// 陷阱状态不是接受状态，并且在所有字符上转移到自身；DFA已经完全时不做任何修改
+    pub fn complete(&mut self) {
+        let missing: Vec<_> = self
+            .graph
+            .edges
+            .iter()
+            .map(|edges| {
+                complement(&normalize(
+                    edges.iter().filter_map(|(_, l)| l.range()).collect(),
+                ))
+            })
+            .collect();
+        if missing.iter().all(|ranges| ranges.is_empty()) {
+            return;
+        }
+
+        let sink = self.graph.edges.len();
+        self.graph.edges.push(Vec::new());
+        if !self.nfa_states.is_empty() {
+            self.nfa_states.push(Vec::new());
+        }
+        for (state, ranges) in missing.into_iter().enumerate() {
+            for range in ranges {
+                self.graph.add_edge(state, sink, Label::from(range));
+            }
+        }
+        for range in universe() {
+            self.graph.add_edge(sink, sink, Label::from(range));
+        }
+        self.graph.merge_ranges();
+    }
 
-        for (i, edge) in edges.iter().enumerate() {
-            let mut to_point = Vec::new();
-            for (to, label) in edge {
-                to_point.push((*to, *label, self.type_of(i)));
+    /// 补DFA：先补全转移，再把接受状态和非接受状态互换，识别所有不被原DFA接受的串
+    ///
+    /// 多个模式的编号没有意义，补DFA的接受状态都对应模式 0
+    pub fn complement(&self) -> DFA {
+        let mut result = self.clone();
+        result.complete();
+        result.end_states = (0..result.graph.edges.len())
+            .filter(|state| !self.end_states.contains(state))
+            .collect();
+        result.accept_tags = result.end_states.iter().map(|&state| (state, 0)).collect();
+        result
+    }
+
+    /// 积构造：两个DFA补全后同时运行，状态为状态对，accept 决定状态对是否接受
+    ///
+    /// 字母表为两个DFA所有边上区间的等价类，结果经过最小化
+    fn product(&self, other: &DFA, accept: fn(bool, bool) -> bool) -> DFA {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        a.complete();
+        b.complete();
+        let labels: Vec<_> = a
+            .graph
+            .edges
+            .iter()
+            .chain(b.graph.edges.iter())
+            .flatten()
+            .filter_map(|(_, label)| label.range())
+            .collect();
+        let terminal = partition(&labels);
+
+        let start = (a.start_state, b.start_state);
+        let mut index_of_state = HashMap::from([(start, 0)]);
+        let mut states = vec![start];
+        let mut graph = Graph_::new(0);
+        let mut end_states = Vec::new();
+        let mut i = 0;
+        while i < states.len() {
+            let (u, v) = states[i];
+            graph.edges.push(Vec::new());
+            for &w in &terminal {
+                let next = (
+                    a.next_state(u, w.start).unwrap(),
+                    b.next_state(v, w.start).unwrap(),
+                );
+                let to = *index_of_state.entry(next).or_insert_with(|| {
+                    states.push(next);
+                    states.len() - 1
+                });
+                graph.add_edge(i, to, Label::from(w));
+            }
+            if accept(a.end_states.contains(&u), b.end_states.contains(&v)) {
+                end_states.push(i);
+            }
+            i += 1;
+        }
+        graph.merge_ranges();
+
+        let mut result = DFA {
+            start_state: 0,
+            accept_tags: end_states.iter().map(|&state| (state, 0)).collect(),
+            end_states,
+            graph,
+            nfa_states: Vec::new(),
+        };
+        result.minimize();
+        result
+    }
+
+    /// 同时被两个DFA接受的串
+    pub fn intersect(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a && b)
+    }
+
+    /// 被任意一个DFA接受的串
+    pub fn union(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a || b)
+    }
+
+    /// 被 self 接受但不被 other 接受的串
+    pub fn difference(&self, other: &DFA) -> DFA {
+        self.product(other, |a, b| a && !b)
+    }
+
+    /// 不接受任何串
+    pub fn is_empty(&self) -> bool {
+        let mut dfa = self.clone();
+        dfa.minimize();
+        dfa.end_states.is_empty()
+    }
+
+    /// 广度优先搜索得到的最短的被接受的串，不接受任何串时返回 `None`
+    pub fn shortest_accepted(&self) -> Option<String> {
+        let mut parent: Vec<Option<(usize, char)>> = vec![None; self.graph.edges.len()];
+        let mut visited = vec![false; self.graph.edges.len()];
+        let mut queue = VecDeque::from([self.start_state]);
+        visited[self.start_state] = true;
+        while let Some(u) = queue.pop_front() {
+            if self.end_states.contains(&u) {
+                let mut result = Vec::new();
+                let mut state = u;
+                while let Some((from, c)) = parent[state] {
+                    result.push(c);
+                    state = from;
+                }
+                return Some(result.into_iter().rev().collect());
             }
-            to_point.sort();
-            if map.contains_key(&to_point) {
-                let x = map.get(&to_point).unwrap();
-                dsu.union(*x, i);
-            } else {
-                map.entry(to_point).or_insert(i);
+            for &(v, label) in &self.graph.edges[u] {
+                if let Some(range) = label.range() {
+                    if !visited[v] {
+                        visited[v] = true;
+                        parent[v] = Some((u, range.representative()));
+                        queue.push_back(v);
+                    }
+                }
             }
         }
+        None
+    }
 
-        self.graph
-            .minimize(&mut dsu, &mut self.start_state, &mut self.end_states);
+    /// `counts[k][state]` 为从 state 出发、长度为 k 的被接受的串的个数，超出 `u128` 时取最大值
+    fn accepted_counts(&self, max_len: usize) -> Vec<Vec<u128>> {
+        let n = self.graph.edges.len();
+        let mut counts = vec![(0..n)
+            .map(|state| self.end_states.contains(&state) as u128)
+            .collect::<Vec<_>>()];
+        for k in 1..=max_len {
+            let row = (0..n)
+                .map(|state| {
+                    self.graph.edges[state]
+                        .iter()
+                        .filter_map(|&(to, label)| Some((to, label.range()?)))
+                        .fold(0u128, |sum, (to, range)| {
+                            sum.saturating_add(
+                                (range.len() as u128).saturating_mul(counts[k - 1][to]),
+                            )
+                        })
+                })
+                .collect();
+            counts.push(row);
+        }
+        counts
+    }
+
+    /// 长度为 len 的被接受的串的个数，动态规划计算，超出 `u128` 时取 `u128::MAX`
+    pub fn count(&self, len: usize) -> u128 {
+        self.accepted_counts(len)[len][self.start_state]
     }
 
-    pub fn show(&self, id: usize) {
+    /// 按长度优先、同长度按字典序（shortlex）列出所有长度不超过 max_len 的被接受的串
+    ///
+    /// 结果按需生成，区间很大时串的个数也很大，可以配合 `take` 使用
+    pub fn enumerate(&self, max_len: usize) -> impl Iterator<Item = String> + '_ {
+        let counts = self.accepted_counts(max_len);
+        // 每个状态的出边按区间排序，字典序即依次取各区间中的字符
+        let edges: Vec<Vec<(CharRange, usize)>> = self
+            .graph
+            .edges
+            .iter()
+            .map(|edges| {
+                let mut edges: Vec<_> = edges
+                    .iter()
+                    .filter_map(|&(to, label)| Some((label.range()?, to)))
+                    .collect();
+                edges.sort();
+                edges
+            })
+            .collect();
+
+        // 深度优先搜索当前长度的串，栈中每一层为 (状态, 当前的边, 当前边上已经取到的字符)
+        let mut len = 0;
+        let mut stack: Vec<(usize, usize, Option<char>)> = Vec::new();
+        let mut prefix = String::new();
+        let mut started = false;
+        std::iter::from_fn(move || loop {
+            if len > max_len {
+                return None;
+            }
+            if !started {
+                started = true;
+                if counts[len][self.start_state] == 0 {
+                    len += 1;
+                    started = false;
+                    continue;
+                }
+                if len == 0 {
+                    len += 1;
+                    started = false;
+                    return Some(String::new());
+                }
+                stack.push((self.start_state, 0, None));
+                prefix.clear();
+            }
+            let depth = stack.len();
+            let Some((state, edge, last)) = stack.last_mut() else {
+                len += 1;
+                started = false;
+                continue;
+            };
+            let Some(&(range, to)) = edges[*state].get(*edge) else {
+                stack.pop();
+                prefix.pop();
+                continue;
+            };
+            // 剩余的长度无法从 to 到达接受状态时跳过整条边
+            let rest = len - depth;
+            let next = match *last {
+                None => Some(range.start),
+                Some(c) => next_char(c).filter(|&c| c <= range.end),
+            };
+            let Some(c) = next.filter(|_| counts[rest][to] > 0) else {
+                *edge += 1;
+                *last = None;
+                continue;
+            };
+            *last = Some(c);
+            if rest == 0 {
+                let mut result = prefix.clone();
+                result.push(c);
+                return Some(result);
+            }
+            prefix.push(c);
+            stack.push((to, 0, None));
+        })
+    }
+
+    /// 在所有长度为 len 的被接受的串中均匀随机地取一个，没有这样的串时返回 `None`
+    ///
+    /// 每一步按照 `accepted_counts` 给出的个数加权选择边，再在区间中均匀选择字符；
+    /// 个数超出 `u128` 时不再严格均匀
+    pub fn sample(&self, len: usize, rng: &mut impl Rng) -> Option<String> {
+        let counts = self.accepted_counts(len);
+        let mut state = self.start_state;
+        let mut result = String::new();
+        for rest in (0..len).rev() {
+            let total = counts[rest + 1][state];
+            if total == 0 {
+                return None;
+            }
+            let mut x = rng.gen_range(0..total);
+            for &(to, label) in &self.graph.edges[state] {
+                let Some(range) = label.range() else {
+                    continue;
+                };
+                let weight = (range.len() as u128).saturating_mul(counts[rest][to]);
+                if x < weight {
+                    let index = (x / counts[rest][to]).min(range.len() as u128 - 1);
+                    result.push(range.nth(index as usize).unwrap());
+                    state = to;
+                    break;
+                }
+                x -= weight;
+            }
+        }
+        self.end_states.contains(&state).then_some(result)
+    }
+
+    /// 判断两个DFA识别的语言是否相同，不同时返回最短的只被其中一个接受的串
+    pub fn equivalent(&self, other: &DFA) -> Result<(), String> {
+        match self.product(other, |a, b| a != b).shortest_accepted() {
+            None => Ok(()),
+            Some(exp) if self.contains(&exp) => Err(format!(
+                "{:?} is accepted by the first dfa but not by the second",
+                exp
+            )),
+            Some(exp) => Err(format!(
+                "{:?} is accepted by the second dfa but not by the first",
+                exp
+            )),
+        }
+    }
+
+    /// 判断 self 识别的语言是否包含于 other，否则返回最短的只被 self 接受的串
+    pub fn is_subset_of(&self, other: &DFA) -> Result<(), String> {
+        match self.difference(other).shortest_accepted() {
+            None => Ok(()),
+            Some(exp) => Err(format!(
+                "{:?} is accepted by the first dfa but not by the second",
+                exp
+            )),
+        }
+    }
+
+    /// 用状态消去法转换为正规表达式，对最小化后的DFA使用时结果更短
+    pub fn to_regex(&self) -> Regex {
+        self.graph.to_regex(self.start_state, &self.end_states)
+    }
+
+    /// 把 DOT 源码写入 dir 目录下的 `dfa_{id}.dot`
+    pub fn show(&self, dir: &Path, id: usize) -> Result<PathBuf, String> {
         println!("start state: {}", self.start_state);
         println!("end states: {:?}", self.end_states);
         self.graph.show_graph(
+            dir,
             &format!("dfa_{}.dot", id),
             self.start_state,
             &self.end_states,
-        );
+        )
+    }
+
+    /// 从 state 读入 c 之后到达的状态
+    fn next_state(&self, state: usize, c: char) -> Option<usize> {
+        self.graph.edges[state]
+            .iter()
+            .find(|&&(_, label)| label.contains(c))
+            .map(|&(to, _)| to)
+    }
+
+    /// 从 text 的第 start 个字节开始向前运行，返回最长的被接受前缀的结束位置（字节）
+    ///
+    /// 运行过程中记录最后一次经过接受状态的位置，没有可以转移的边时停止；
+    /// 开始状态为接受状态时空前缀也会被接受，此时返回 `Some(start)`
+    pub fn longest_prefix(&self, text: &str, start: usize) -> Option<usize> {
+        self.longest_match(text, start).map(|(end, _)| end)
+    }
+
+    /// 与 `longest_prefix` 相同，同时返回最后经过的接受状态对应的模式编号
+    pub fn longest_match(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let mut state = self.start_state;
+        let mut last_accept = self.accept_tags.get(&state).map(|&tag| (start, tag));
+        for (offset, c) in text[start..].char_indices() {
+            match self.next_state(state, c) {
+                Some(next) => state = next,
+                None => break,
+            }
+            if let Some(&tag) = self.accept_tags.get(&state) {
+                last_accept = Some((start + offset + c.len_utf8(), tag));
+            }
+        }
+        last_accept
     }
 
     pub fn contains(&self, exp: &str) -> bool {
-        let mut succeed = false;
-        self.graph
-            .dfs_search(self.start_state, &self.end_states, 0, exp, &mut succeed)
+        self.graph.simulate(self.start_state, &self.end_states, exp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// 随机生成深度不超过 depth 的表达式
+    fn random_regex(rng: &mut StdRng, depth: usize) -> String {
+        const ATOMS: [&str; 8] = ["a", "b", "c", "[a-c]", "[^a]", ".", "(?:)", "ε"];
+        if depth == 0 || rng.gen_bool(0.3) {
+            return ATOMS[rng.gen_range(0..ATOMS.len())].to_string();
+        }
+        let sub = random_regex(rng, depth - 1);
+        match rng.gen_range(0..8) {
+            0 | 1 => format!("{}{}", sub, random_regex(rng, depth - 1)),
+            2 | 3 => format!("(?:{}|{})", sub, random_regex(rng, depth - 1)),
+            4 => format!("(?:{})*", sub),
+            5 => format!("(?:{})+", sub),
+            6 => format!("({})?", sub),
+            _ => {
+                let min = rng.gen_range(0..3);
+                format!("(?:{}){{{},{}}}", sub, min, min + rng.gen_range(0..3))
+            }
+        }
+    }
+
+    #[test]
+    fn derivatives_agree_with_subset_construction() {
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..300 {
+            let pattern = random_regex(&mut rng, 4);
+            let regex = Regex::parse(&pattern).unwrap();
+            let by_derivatives = DFA::from_derivatives(&regex);
+            let by_subsets = DFA::from(&NFA::from(pattern.as_str()));
+            assert_eq!(
+                by_derivatives.equivalent(&by_subsets),
+                Ok(()),
+                "{}",
+                pattern
+            );
+            let mut minimized = by_subsets.clone();
+            minimized.minimize();
+            assert_eq!(minimized.equivalent(&by_subsets), Ok(()), "{}", pattern);
+        }
     }
 }
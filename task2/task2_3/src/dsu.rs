@@ -5,11 +5,8 @@ pub struct DSU {
 
 impl DSU {
     pub fn new(n: usize) -> Self {
-        let mut parent = vec![0; n];
+        let parent = (0..n).collect();
         let rank = vec![0; n];
-        for i in 0..n {
-            parent[i] = i;
-        }
         DSU { parent, rank }
     }
 
@@ -45,8 +42,4 @@ impl DSU {
         }
         result
     }
-
-    pub fn show(&self) {
-        println!("parent: {:?}", self.parent);
-    }
 }
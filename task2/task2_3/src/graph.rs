@@ -1,13 +1,69 @@
+use crate::charset::{escape_char, normalize, partition, CharRange};
 use crate::dsu::DSU;
-use petgraph::dot::Dot;
-use petgraph::graph::DiGraph;
-use rand::seq::SliceRandom;
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
-use std::{fs, usize};
+use crate::regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+/// 边上的标签
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Label {
+    /// 不读入字符的 ε 转移
+    Epsilon,
+    Char(char),
+    /// 读入区间内的任意一个字符
+    Range(CharRange),
+}
+
+impl Label {
+    /// 单个字符的区间转为 `Label::Char`
+    pub fn from(range: CharRange) -> Self {
+        if range.start == range.end {
+            Label::Char(range.start)
+        } else {
+            Label::Range(range)
+        }
+    }
+
+    /// 读入 c 时能否沿这条边转移，ε 边不读入字符
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            Label::Epsilon => false,
+            Label::Char(x) => *x == c,
+            Label::Range(range) => range.contains(c),
+        }
+    }
+
+    /// 标签对应的字符区间，ε 边返回 `None`
+    pub fn range(&self) -> Option<CharRange> {
+        match self {
+            Label::Epsilon => None,
+            Label::Char(c) => Some(CharRange::single(*c)),
+            Label::Range(range) => Some(*range),
+        }
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Label::Epsilon => write!(f, "ε"),
+            Label::Char(c) => write!(f, "{}", escape_char(*c)),
+            Label::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
+
+/// 捕获位置的槽位，第 `2k` 和 `2k + 1` 个槽位为第 `k` 组的起止位置
+pub type Slots = Vec<Option<usize>>;
+
+#[derive(Clone)]
 pub struct Graph_ {
-    pub edges: Vec<Vec<(usize, char)>>,
+    /// 每条边的标签是一个字符区间，区间内的任意字符都可以沿这条边转移
+    pub edges: Vec<Vec<(usize, Label)>>,
+    /// 带标记的状态，经过该状态时把当前位置记录到对应的槽位
+    pub tags: HashMap<usize, usize>,
 }
 
 impl Graph_ {
@@ -16,7 +72,10 @@ impl Graph_ {
         for _ in 0..len {
             edges.push(Vec::new());
         }
-        Graph_ { edges }
+        Graph_ {
+            edges,
+            tags: HashMap::new(),
+        }
     }
 
     fn alloc(point: usize, allocotor: &mut HashMap<usize, usize>) -> usize {
@@ -28,7 +87,7 @@ impl Graph_ {
     }
 
     fn remove_multiple_edges(&mut self) {
-        for (_u, edges) in self.edges.iter_mut().enumerate() {
+        for edges in self.edges.iter_mut() {
             let mut set = HashSet::new();
             let mut result = Vec::new();
             for item in edges.iter() {
@@ -45,11 +104,11 @@ impl Graph_ {
         dsu: &mut DSU,
         start_state: &mut usize,
         end_states: &mut Vec<usize>,
+        accept_tags: &mut HashMap<usize, usize>,
     ) {
         let mut point_alloctor: HashMap<usize, usize> = HashMap::new();
         let mut p = |x| Graph_::alloc(x, &mut point_alloctor);
         let mut graph = Graph_::new(dsu.count());
-        dsu.show();
 
         for (u, edges) in self.edges.iter().enumerate() {
             for (v, w) in edges {
@@ -62,163 +121,439 @@ impl Graph_ {
         graph.remove_multiple_edges();
 
         self.edges = graph.edges.clone();
-        *start_state = p(*start_state);
-        for end_state in end_states {
-            *end_state = p(*end_state);
+        *start_state = p(dsu.find(*start_state));
+        for end_state in end_states.iter_mut() {
+            *end_state = p(dsu.find(*end_state));
         }
+        end_states.sort();
+        end_states.dedup();
+        *accept_tags = accept_tags
+            .iter()
+            .map(|(&state, &tag)| (p(dsu.find(state)), tag))
+            .collect();
     }
 
-    pub fn add_edge(&mut self, from: usize, to: usize, label: char) {
+    /// 合并从同一状态出发、到达同一状态的边上的区间，只用于没有 ε 边的DFA
+    pub fn merge_ranges(&mut self) {
+        for edges in self.edges.iter_mut() {
+            let mut targets: HashMap<usize, Vec<CharRange>> = HashMap::new();
+            for &(to, label) in edges.iter() {
+                targets.entry(to).or_default().extend(label.range());
+            }
+            let mut result = Vec::new();
+            for (to, ranges) in targets {
+                for range in normalize(ranges) {
+                    result.push((to, Label::from(range)));
+                }
+            }
+            result.sort_by_key(|&(to, label)| (label.range(), to));
+            *edges = result;
+        }
+    }
+
+    pub fn add_edge(&mut self, from: usize, to: usize, label: Label) {
         self.edges[from].push((to, label));
     }
 
-    fn dfs_only_epsilon(&self, point: usize, visited: &mut Vec<bool>, result: &mut Vec<usize>) {
-        visited[point] = true;
-        for &(to, label) in &self.edges[point] {
-            if label == 'ε' && !visited[to] {
-                result.push(to);
-                self.dfs_only_epsilon(to, visited, result);
+    /// ε-closure of a set of points, sorted
+    pub fn epsilon_closure(&self, points: &[usize]) -> Vec<usize> {
+        let mut visited = vec![false; self.edges.len()];
+        let mut stack = Vec::new();
+        for &point in points {
+            if !visited[point] {
+                visited[point] = true;
+                stack.push(point);
             }
         }
+        while let Some(u) = stack.pop() {
+            for &(to, label) in &self.edges[u] {
+                if label == Label::Epsilon && !visited[to] {
+                    visited[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+        (0..self.edges.len()).filter(|&i| visited[i]).collect()
     }
 
     pub fn extend_from_point(&self, point: usize) -> Vec<usize> {
-        let mut result = Vec::new();
-        result.push(point);
-        let mut visited = vec![false; self.edges.len()];
-        self.dfs_only_epsilon(point, &mut visited, &mut result);
-        result
+        self.epsilon_closure(&[point])
     }
 
-    pub fn dfs_search(
-        &self,
-        u: usize,
-        end_states: &Vec<usize>,
-        now_index: usize,
-        exp: &str,
-        has_succeed: &mut bool,
-    ) -> bool {
-        // println!("u: {}, now_index: {}, exp: {}", u, now_index, exp);
-        if now_index == exp.len() {
-            if end_states.contains(&u) {
-                *has_succeed = true;
-                return true;
+    /// 状态消去法：把边上的标签看作正规表达式，依次消去每个状态，最后得到等价的正规表达式
+    ///
+    /// 额外加入新的开始状态和结束状态，消去状态 k 时对每对 i -> k -> j 加入边
+    /// `R(i,k) R(k,k)* R(k,j)`，每次优先消去入度与出度乘积最小的状态；不接受任何串时返回空字符类
+    pub fn to_regex(&self, start_state: usize, end_states: &[usize]) -> Regex {
+        let n = self.edges.len();
+        let (start, end) = (n, n + 1);
+        // table[i][j] 为从 i 到 j 的边上的正规表达式
+        let mut table: Vec<BTreeMap<usize, Regex>> = vec![BTreeMap::new(); n + 2];
+        let add = |table: &mut Vec<BTreeMap<usize, Regex>>, i: usize, j: usize, regex: Regex| {
+            let regex = match table[i].remove(&j) {
+                Some(old) => Regex::Alt(vec![old, regex]).simplify(),
+                None => regex,
+            };
+            table[i].insert(j, regex);
+        };
+
+        add(&mut table, start, start_state, Regex::Empty);
+        for &end_state in end_states {
+            add(&mut table, end_state, end, Regex::Empty);
+        }
+        for (u, edges) in self.edges.iter().enumerate() {
+            for &(v, label) in edges {
+                let regex = match label {
+                    Label::Epsilon => Regex::Empty,
+                    Label::Char(c) => Regex::Char(c),
+                    Label::Range(range) => Regex::Class(vec![range]),
+                };
+                add(&mut table, u, v, regex);
             }
         }
-        if *has_succeed {
-            return true;
+
+        let mut remaining: Vec<usize> = (0..n).collect();
+        while !remaining.is_empty() {
+            let (index, &k) = remaining
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, &k)| {
+                    let incoming = table.iter().filter(|row| row.contains_key(&k)).count();
+                    incoming * table[k].len()
+                })
+                .unwrap();
+            remaining.swap_remove(index);
+
+            let repeat = table[k]
+                .remove(&k)
+                .map(|regex| Regex::Star(Box::new(regex)));
+            let outgoing = std::mem::take(&mut table[k]);
+            for i in 0..n + 2 {
+                let Some(incoming) = table[i].remove(&k) else {
+                    continue;
+                };
+                for (&j, out) in &outgoing {
+                    let mut items = vec![incoming.clone()];
+                    items.extend(repeat.clone());
+                    items.push(out.clone());
+                    add(&mut table, i, j, Regex::Concat(items).simplify());
+                }
+            }
         }
-        let mut result = false;
 
-        let mut edges = self.edges[u].clone();
-        let mut rng = rand::thread_rng();
-        edges.shuffle(&mut rng);
+        table[start]
+            .remove(&end)
+            .unwrap_or_else(|| Regex::Class(Vec::new()))
+    }
 
-        for (v, w) in &edges {
-            if *w == 'ε' {
-                result |= self.dfs_search(*v, end_states, now_index, exp, has_succeed);
+    /// 同时维护所有可能到达的状态，每读入一个字符计算一次转移和 ε-closure，复杂度为 O(n·m)
+    pub fn simulate(&self, start_state: usize, end_states: &[usize], exp: &str) -> bool {
+        let mut states = self.extend_from_point(start_state);
+        for c in exp.chars() {
+            if states.is_empty() {
+                return false;
             }
-            if now_index == exp.len() {
+            states = self.extend_from_point_vec_and_val(&states, c);
+        }
+        states.iter().any(|state| end_states.contains(state))
+    }
+
+    /// 按边的优先级把 state 及其 ε-closure 加入线程列表，经过带标记的状态时记录 pos
+    fn add_thread(
+        &self,
+        threads: &mut Vec<(usize, Slots)>,
+        visited: &mut [bool],
+        state: usize,
+        slots: Slots,
+        pos: usize,
+    ) {
+        let mut stack = vec![(state, slots)];
+        while let Some((u, mut slots)) = stack.pop() {
+            if visited[u] {
                 continue;
             }
-            if *w == exp.chars().nth(now_index).unwrap() {
-                result |= self.dfs_search(*v, end_states, now_index + 1, exp, has_succeed);
+            visited[u] = true;
+            if let Some(&slot) = self.tags.get(&u) {
+                slots[slot] = Some(pos);
+            }
+            // 逆序入栈，保证先走优先级高的边
+            for &(to, label) in self.edges[u].iter().rev() {
+                if label == Label::Epsilon && !visited[to] {
+                    stack.push((to, slots.clone()));
+                }
             }
+            threads.push((u, slots));
         }
-        result
     }
 
-    pub fn extend_from_point_vec_and_val(&self, point: &Vec<usize>, val: char) -> Vec<usize> {
-        let mut result = HashSet::new();
-        for &point in point {
-            for (to, label) in &self.edges[point] {
-                if *label == val {
-                    for x in self.extend_from_point(*to) {
-                        result.insert(x);
+    /// 从 text 的第 start 个字节开始查找最左的匹配，返回各个槽位记录的字节位置
+    ///
+    /// 线程按优先级排列，或运算优先左侧分支，闭包优先多匹配，找到匹配后丢弃优先级更低的线程
+    pub fn search(
+        &self,
+        start_state: usize,
+        end_state: usize,
+        text: &str,
+        start: usize,
+        slot_count: usize,
+    ) -> Option<Slots> {
+        let mut threads = Vec::new();
+        let mut visited = vec![false; self.edges.len()];
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            // 尚未找到匹配时，从当前位置开始一个新的匹配
+            if matched.is_none() {
+                let mut slots = vec![None; slot_count];
+                slots[0] = Some(pos);
+                self.add_thread(&mut threads, &mut visited, start_state, slots, pos);
+            }
+            if threads.is_empty() {
+                break;
+            }
+
+            let c = text[pos..].chars().next();
+            let mut next_threads = Vec::new();
+            let mut next_visited = vec![false; self.edges.len()];
+            for (state, mut slots) in threads {
+                if state == end_state {
+                    slots[1] = Some(pos);
+                    matched = Some(slots);
+                    break;
+                }
+                if let Some(c) = c {
+                    for &(to, label) in &self.edges[state] {
+                        if label.contains(c) {
+                            let next_pos = pos + c.len_utf8();
+                            self.add_thread(
+                                &mut next_threads,
+                                &mut next_visited,
+                                to,
+                                slots.clone(),
+                                next_pos,
+                            );
+                        }
                     }
                 }
             }
+
+            match c {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            threads = next_threads;
+            visited = next_visited;
         }
-        let mut to_state: Vec<usize> = result.clone().into_iter().collect();
-        to_state.sort();
-        to_state
+        matched
     }
 
-    pub fn dfs_get_dfa_states(
-        &self,
-        u: &Vec<usize>,
-        terminate_states: &Vec<char>,
-        states: &mut HashSet<Vec<usize>>,
-    ) {
-        for w in terminate_states {
-            let to_state = self.extend_from_point_vec_and_val(u, *w);
-            if !states.contains(&to_state) && !to_state.is_empty() {
-                states.insert(to_state.clone());
-                self.dfs_get_dfa_states(&to_state, terminate_states, states);
+    pub fn extend_from_point_vec_and_val(&self, point: &[usize], val: char) -> Vec<usize> {
+        let mut to_points = Vec::new();
+        for &point in point {
+            for &(to, label) in &self.edges[point] {
+                if label.contains(val) {
+                    to_points.push(to);
+                }
             }
         }
+        self.epsilon_closure(&to_points)
     }
 
-    pub fn get_terminal(&self) -> Vec<char> {
-        let mut terminal = HashSet::new();
-        for edges in &self.edges {
-            for (_, label) in edges {
-                terminal.insert(*label);
+    /// 把所有非 ε 边的标签划分为互不相交的区间，同一区间内的字符转移完全相同
+    pub fn get_terminal(&self) -> Vec<CharRange> {
+        let labels: Vec<CharRange> = self
+            .edges
+            .iter()
+            .flatten()
+            .filter_map(|&(_, label)| label.range())
+            .collect();
+        partition(&labels)
+    }
+
+    /// 状态 × 字符区间的转移表，每一格为读入该区间内的字符后到达的状态集合，
+    /// 有 ε 边时最后一列为 ε；subsets 不为空时额外列出每个状态对应的NFA状态集合
+    pub fn transition_table(
+        &self,
+        start_state: usize,
+        accept_states: &[usize],
+        subsets: &[Vec<usize>],
+    ) -> String {
+        let terminal = self.get_terminal();
+        let has_epsilon = self
+            .edges
+            .iter()
+            .flatten()
+            .any(|&(_, l)| l == Label::Epsilon);
+        let fmt_set = |states: &[usize]| match states {
+            [] => "-".to_string(),
+            [state] => state.to_string(),
+            _ => {
+                let states: Vec<String> = states.iter().map(usize::to_string).collect();
+                format!("{{{}}}", states.join(","))
             }
+        };
+
+        let mut header = vec![String::new(), "state".to_string()];
+        if !subsets.is_empty() {
+            header.push("nfa states".to_string());
+        }
+        header.extend(terminal.iter().map(CharRange::to_string));
+        // 字符区间的表头是单个字符或 `a-b`，`(ε)` 不会与它们重名
+        if has_epsilon {
+            header.push("(ε)".to_string());
+        }
+        let mut rows = vec![header];
+        for (state, edges) in self.edges.iter().enumerate() {
+            let mut marker = String::new();
+            if state == start_state {
+                marker.push('→');
+            }
+            if accept_states.contains(&state) {
+                marker.push('*');
+            }
+            let mut row = vec![marker, state.to_string()];
+            if !subsets.is_empty() {
+                let states: Vec<String> = subsets[state].iter().map(usize::to_string).collect();
+                row.push(format!("{{{}}}", states.join(",")));
+            }
+            let targets = |matches: &dyn Fn(Label) -> bool| {
+                let mut targets: Vec<usize> = edges
+                    .iter()
+                    .filter(|&&(_, label)| matches(label))
+                    .map(|&(to, _)| to)
+                    .collect();
+                targets.sort();
+                targets.dedup();
+                fmt_set(&targets)
+            };
+            for range in &terminal {
+                row.push(targets(&|label| label.contains(range.start)));
+            }
+            if has_epsilon {
+                row.push(targets(&|label| label == Label::Epsilon));
+            }
+            rows.push(row);
         }
-        terminal.remove(&'ε');
-        let mut res: Vec<char> = terminal.clone().into_iter().collect();
-        res.sort();
-        res
-    }
 
-    pub fn show_graph(&self, filename: &str, start_state: usize, accept_states: &[usize]) {
-        let mut graph = DiGraph::new();
+        // 按列对齐
+        let columns = rows[0].len();
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap())
+            .collect();
+        let mut table = String::new();
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| {
+                    let padding = width - cell.chars().count();
+                    format!("{}{}", cell, " ".repeat(padding))
+                })
+                .collect();
+            table.push_str(cells.join(" | ").trim_end());
+            table.push('\n');
+        }
+        table
+    }
 
-        let mut node_indices = vec![];
-        for i in 0..self.edges.len() {
-            node_indices.push(graph.add_node(format!("{}", i)));
+    /// 生成 Graphviz 的 DOT 源码：从左到右排列，`start` 箭头指向开始状态，接受状态为双圈，
+    /// 同一对状态之间的多条边合并为一条，标签用 `,` 分隔
+    pub fn to_dot(&self, start_state: usize, accept_states: &[usize]) -> String {
+        let mut dot = String::from("digraph {\n    rankdir=LR;\n");
+        dot.push_str("    start [shape=point];\n");
+        for state in 0..self.edges.len() {
+            let shape = if accept_states.contains(&state) {
+                "doublecircle"
+            } else {
+                "circle"
+            };
+            dot.push_str(&format!(
+                "    {} [label=\"{}\", shape={}];\n",
+                state, state, shape
+            ));
         }
+        dot.push_str(&format!("    start -> {};\n", start_state));
         for (from, edges) in self.edges.iter().enumerate() {
+            // 按第一次出现的顺序合并到达同一状态的边
+            let mut targets: Vec<(usize, Vec<String>)> = Vec::new();
             for &(to, label) in edges {
-                graph.add_edge(node_indices[from], node_indices[to], label.to_string());
+                let label = label.to_string();
+                match targets.iter_mut().find(|(target, _)| *target == to) {
+                    Some((_, labels)) => labels.push(label),
+                    None => targets.push((to, vec![label])),
+                }
+            }
+            for (to, labels) in targets {
+                dot.push_str(&format!(
+                    "    {} -> {} [label=\"{}\"];\n",
+                    from,
+                    to,
+                    escape_dot(&labels.join(","))
+                ));
             }
         }
+        dot.push_str("}\n");
+        dot
+    }
 
-        // 添加开始状态
-        let start_node = graph.add_node("start".to_string());
-        graph.add_edge(start_node, node_indices[start_state], "".to_string());
-
-        // 生成 DOT 格式的字符串
-        let dot = Dot::new(&graph);
-        let mut dot_string = format!("{:?}", dot);
-
-        // 设置图的方向为从左到右
-        dot_string = dot_string.replace("digraph {", "digraph {\n    rankdir=LR;");
-        dot_string = dot_string.replace("\\\"", "");
+    /// 把 DOT 源码写入 dir 目录下的 filename，返回文件的路径，需要图片时再调用 `render_dot`
+    pub fn show_graph(
+        &self,
+        dir: &Path,
+        filename: &str,
+        start_state: usize,
+        accept_states: &[usize],
+    ) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        let dot_file = dir.join(filename);
+        fs::write(&dot_file, self.to_dot(start_state, accept_states))
+            .map_err(|err| format!("failed to write {}: {}", dot_file.display(), err))?;
+        Ok(dot_file)
+    }
+}
 
-        // 标记接受状态
-        for &accept_state in accept_states {
-            // let node_label = format!("\"{}\"", accept_state);
-            // println!("# accept_state: {}", node_label);
-            dot_string = dot_string.replace(
-                &format!("    {} [ ", accept_state),
-                &format!("    {} [ shape=doublecircle, ", accept_state),
-            );
-        }
+/// 转义 DOT 字符串中的 `\` 和 `"`
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
 
-        let path = Path::new("result_pic");
-        fs::create_dir_all(path).expect("Unable to create directory");
+/// 调用 Graphviz 把 DOT 文件渲染为图片，format 为 `png`、`svg` 等，返回图片的路径
+///
+/// 没有安装 Graphviz 或渲染失败时返回错误信息，不会 panic
+pub fn render_dot(dot_file: &Path, format: &str) -> Result<PathBuf, String> {
+    let output = dot_file.with_extension(format);
+    let result = std::process::Command::new("dot")
+        .arg(format!("-T{}", format))
+        .arg(dot_file)
+        .arg("-o")
+        .arg(&output)
+        .output();
+    match result {
+        Ok(result) if result.status.success() => Ok(output),
+        Ok(result) => Err(format!(
+            "dot failed to render {}: {}",
+            dot_file.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(format!(
+            "Graphviz `dot` was not found, install Graphviz to render {}",
+            dot_file.display()
+        )),
+        Err(err) => Err(format!("failed to run dot: {}", err)),
+    }
+}
 
-        let dot_file_path = path.join(filename);
-        let dot_file_path_str = dot_file_path.to_str().unwrap();
-        std::fs::write(dot_file_path_str, dot_string).expect("Unable to write file");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let png_file_path = path.join(filename.replace(".dot", ".png"));
-        let png_file_path_str = png_file_path.to_str().unwrap();
-        std::process::Command::new("dot")
-            .args(&["-Tpng", dot_file_path_str, "-o", png_file_path_str])
-            .output()
-            .expect("Failed to execute dot command");
+    #[test]
+    fn epsilon_moves_and_the_char_epsilon_differ() {
+        assert_ne!(Label::Epsilon.to_string(), Label::Char('ε').to_string());
+        assert_ne!(
+            Label::Epsilon.to_string(),
+            Label::Range(CharRange::new('δ', 'ε')).to_string()
+        );
     }
 }
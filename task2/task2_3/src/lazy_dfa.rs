@@ -0,0 +1,153 @@
+use crate::nfa::NFA;
+use std::collections::HashMap;
+
+/// 缓存最多保存的DFA状态数
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// 一次匹配中缓存被清空超过该次数时，认为缓存在反复抖动，改用NFA模拟完成剩余的匹配
+const MAX_CACHE_CLEARS: usize = 3;
+
+/// 在匹配过程中按需进行子集构造的DFA
+///
+/// 只有实际走到的状态子集才会成为DFA状态，并缓存其转移；`(a|b)*a(a|b){20}` 这类
+/// 完整子集构造会产生指数个状态的表达式也可以使用。缓存满时清空，
+/// 频繁清空时退化为NFA的状态集合模拟，保证每个字符的代价不超过 O(n)
+pub struct LazyDFA {
+    nfa: NFA,
+    capacity: usize,
+    /// 每个DFA状态对应的NFA状态集合
+    states: Vec<Vec<usize>>,
+    index_of_state: HashMap<Vec<usize>, usize>,
+    /// 已经计算过的转移，`None` 表示到达死状态
+    transitions: Vec<HashMap<char, Option<usize>>>,
+    /// 累计清空缓存的次数
+    pub cache_clears: usize,
+    /// 累计退化为NFA模拟的次数
+    pub fallbacks: usize,
+}
+
+impl LazyDFA {
+    pub fn from(nfa: &NFA) -> Self {
+        LazyDFA::with_capacity(nfa, DEFAULT_CACHE_CAPACITY)
+    }
+
+    pub fn with_capacity(nfa: &NFA, capacity: usize) -> Self {
+        assert!(capacity >= 2, "error: the cache needs at least 2 states");
+        LazyDFA {
+            nfa: nfa.clone(),
+            capacity,
+            states: Vec::new(),
+            index_of_state: HashMap::new(),
+            transitions: Vec::new(),
+            cache_clears: 0,
+            fallbacks: 0,
+        }
+    }
+
+    /// 当前缓存中的DFA状态数
+    pub fn cached_states(&self) -> usize {
+        self.states.len()
+    }
+
+    fn clear_cache(&mut self) {
+        self.states.clear();
+        self.index_of_state.clear();
+        self.transitions.clear();
+        self.cache_clears += 1;
+    }
+
+    /// 把状态集合加入缓存，返回其编号
+    fn add_state(&mut self, subset: Vec<usize>) -> usize {
+        if let Some(&index) = self.index_of_state.get(&subset) {
+            return index;
+        }
+        self.index_of_state
+            .insert(subset.clone(), self.states.len());
+        self.states.push(subset);
+        self.transitions.push(HashMap::new());
+        self.states.len() - 1
+    }
+
+    fn is_accept(&self, subset: &[usize]) -> bool {
+        subset.contains(&(self.nfa.graph.edges.len() - 1))
+    }
+
+    pub fn contains(&mut self, exp: &str) -> bool {
+        let start = self.nfa.graph.extend_from_point(0);
+        let mut state = self.add_state(start);
+        let mut clears = 0;
+        let mut chars = exp.chars();
+        while let Some(c) = chars.next() {
+            if let Some(&next) = self.transitions[state].get(&c) {
+                match next {
+                    Some(next) => state = next,
+                    None => return false,
+                }
+                continue;
+            }
+
+            let subset = self
+                .nfa
+                .graph
+                .extend_from_point_vec_and_val(&self.states[state], c);
+            if subset.is_empty() {
+                self.transitions[state].insert(c, None);
+                return false;
+            }
+            if !self.index_of_state.contains_key(&subset) && self.states.len() >= self.capacity {
+                if clears == MAX_CACHE_CLEARS {
+                    // 缓存在反复抖动，剩余部分直接模拟NFA
+                    self.fallbacks += 1;
+                    let mut subset = subset;
+                    for c in chars {
+                        if subset.is_empty() {
+                            return false;
+                        }
+                        subset = self.nfa.graph.extend_from_point_vec_and_val(&subset, c);
+                    }
+                    return self.is_accept(&subset);
+                }
+                clears += 1;
+                self.clear_cache();
+                state = self.add_state(subset);
+                continue;
+            }
+            let next = self.add_state(subset);
+            self.transitions[state].insert(c, Some(next));
+            state = next;
+        }
+        self.is_accept(&self.states[state])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_cache_falls_back_to_nfa_simulation() {
+        let nfa = NFA::from("(a|b)*a(a|b){5}");
+        let mut lazy = LazyDFA::with_capacity(&nfa, 2);
+        let texts = [
+            "abbabbaab",
+            "babaabbbabab",
+            "aaaaaa",
+            "abbbbb",
+            "bbbbbb",
+            "abbabbbab",
+            "",
+        ];
+        // 记录每个串匹配时是否退化为NFA模拟，接受和不接受的串都要覆盖到
+        let mut fell_back = Vec::new();
+        for text in texts {
+            let fallbacks = lazy.fallbacks;
+            let accepted = lazy.contains(text);
+            assert_eq!(accepted, nfa.contains(text), "{:?}", text);
+            if lazy.fallbacks > fallbacks {
+                fell_back.push(accepted);
+            }
+        }
+        assert!(lazy.fallbacks > 0);
+        assert!(fell_back.contains(&true) && fell_back.contains(&false));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::codegen::generate_rust;
+use crate::dfa::DFA;
+use crate::nfa::{or, NFA};
+use crate::regex::RegexError;
+use std::ops::Range;
+
+/// 没有任何模式能够匹配时，单个字符产生的词法单元名称
+pub const ERROR_TOKEN: &str = "Error";
+
+/// 由多个带名称的正规表达式生成的词法分析器
+///
+/// 所有模式的NFA通过 `or` 合并为一个NFA，再子集构造为一个DFA；
+/// 分析时取最长匹配，长度相同时取在规则列表中靠前的模式
+pub struct LexerSpec {
+    names: Vec<String>,
+    dfa: DFA,
+}
+
+impl TryFrom<&[(&str, &str)]> for LexerSpec {
+    type Error = RegexError;
+
+    fn try_from(rules: &[(&str, &str)]) -> Result<Self, Self::Error> {
+        let mut names = Vec::new();
+        let mut union: Option<NFA> = None;
+        // 每个模式的接受状态在合并后的NFA中的编号
+        let mut accepts = Vec::new();
+        for &(name, exp) in rules {
+            let nfa = NFA::try_from(exp)?;
+            names.push(name.to_string());
+            union = Some(match union {
+                None => {
+                    accepts.push(nfa.graph.edges.len() - 1);
+                    nfa
+                }
+                Some(union) => {
+                    // or 把左侧的状态整体后移 1 位，右侧的状态后移 左侧状态数 + 1 位
+                    for accept in accepts.iter_mut() {
+                        *accept += 1;
+                    }
+                    accepts.push(union.graph.edges.len() + nfa.graph.edges.len());
+                    or(&union, &nfa)
+                }
+            });
+        }
+        // 没有任何规则时没有可以出错的表达式，错误位置为空表达式的开头
+        let union = union
+            .ok_or_else(|| RegexError::new("", 0, "a lexer needs at least one rule", "a rule"))?;
+        Ok(LexerSpec {
+            names,
+            dfa: DFA::from_accepts(&union, &accepts),
+        })
+    }
+}
+
+impl LexerSpec {
+    /// 表达式不合法时 panic 并打印错误位置，需要处理错误时使用 `LexerSpec::try_from`
+    pub fn from(rules: &[(&str, &str)]) -> Self {
+        LexerSpec::try_from(rules).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// 最小化内部的DFA，不同模式的接受状态不会被合并
+    pub fn minimize(&mut self) {
+        self.dfa.minimize();
+    }
+
+    /// 生成不依赖本项目的 Rust 词法分析器源码，见 `codegen::generate_rust`
+    pub fn generate_rust(&self) -> String {
+        generate_rust(&self.dfa, &self.names)
+    }
+
+    /// 按最长匹配切分 text，返回词法单元名称及其字节范围
+    ///
+    /// 无法匹配的字符单独作为一个 `ERROR_TOKEN`，空匹配不会产生词法单元
+    pub fn tokenize(&self, text: &str) -> Vec<(&str, Range<usize>)> {
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start < text.len() {
+            match self.dfa.longest_match(text, start) {
+                Some((end, tag)) if end > start => {
+                    result.push((self.names[tag].as_str(), start..end));
+                    start = end;
+                }
+                _ => {
+                    let end = start + text[start..].chars().next().unwrap().len_utf8();
+                    result.push((ERROR_TOKEN, start..end));
+                    start = end;
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_rules_are_an_error() {
+        let rules: &[(&str, &str)] = &[];
+        let err = LexerSpec::try_from(rules).err().unwrap();
+        assert_eq!(err.message, "a lexer needs at least one rule");
+    }
+}
@@ -1,18 +1,42 @@
+#![allow(clippy::upper_case_acronyms)]
 #[macro_use]
 extern crate lazy_static;
+use charset::{normalize, CharRange};
 use dfa::DFA;
+use graph::render_dot;
+use lazy_dfa::LazyDFA;
+use lexer::LexerSpec;
 use nfa::NFA;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use regex::Regex;
+use serialize::Automaton;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Mutex;
+mod charset;
+mod cli;
+mod codegen;
+mod derivative;
 mod dfa;
 mod dsu;
 mod graph;
+mod lazy_dfa;
+mod lexer;
 mod nfa;
+mod regex;
+mod serialize;
 
 lazy_static! {
     static ref TEST_ID: Mutex<usize> = Mutex::new(0);
 }
 
+/// 测试输出的 DOT 文件所在的目录
+const PICTURE_DIR: &str = "result_pic";
+
 struct Test {
+    regex: Regex,
     nfa: NFA,
     dfa: DFA,
     regular_expression: String,
@@ -20,11 +44,14 @@ struct Test {
 }
 
 impl Test {
-    pub fn from(regular_expression: String, expression: Vec<&str>) -> Self {
-        let expression = expression.iter().map(|x| x.to_string()).collect();
+    /// 测试用的串由DFA生成，见 `examples`
+    pub fn from(regular_expression: String) -> Self {
+        let regex = Regex::parse(&regular_expression).unwrap_or_else(|err| panic!("{}", err));
         let nfa = NFA::from(&regular_expression);
         let dfa = DFA::from(&nfa);
+        let expression = examples(&dfa);
         Test {
+            regex,
             nfa,
             dfa,
             regular_expression,
@@ -37,11 +64,19 @@ impl Test {
             "========test {} for nfa begin!========",
             TEST_ID.lock().unwrap()
         );
-        println!("regular expression: {}\n", self.regular_expression);
-        self.nfa.show(*TEST_ID.lock().unwrap());
+        println!("regular expression: {}", self.regular_expression);
+        println!("parsed expression: {}", self.regex);
+        println!("simplified expression: {}\n", self.regex.simplify());
+        if let Err(err) = self
+            .nfa
+            .show(Path::new(PICTURE_DIR), *TEST_ID.lock().unwrap())
+        {
+            println!("{}", err);
+        }
+        println!("{}", self.nfa);
 
         for exp in &self.expression {
-            println!("expression: {}", exp);
+            println!("expression: {:?}", exp);
             let identified = self.nfa.contains(exp);
             println!("identified: {}\n", identified);
         }
@@ -50,31 +85,67 @@ impl Test {
             "========test {} for dfa begin!========",
             TEST_ID.lock().unwrap()
         );
-        println!("regular expression: {}\n", self.regular_expression);
-        self.dfa.show(*TEST_ID.lock().unwrap());
+        println!("regular expression: {}", self.regular_expression);
+        println!("parsed expression: {}", self.regex);
+        println!("simplified expression: {}\n", self.regex.simplify());
+        if let Err(err) = self
+            .dfa
+            .show(Path::new(PICTURE_DIR), *TEST_ID.lock().unwrap())
+        {
+            println!("{}", err);
+        }
+        println!("{}", self.dfa);
 
         for exp in &self.expression {
-            println!("expression: {}", exp);
+            println!("expression: {:?}", exp);
             let identified = self.dfa.contains(exp);
             println!("identified: {}\n", identified);
         }
 
+        // 与直接由子集构造得到的DFA比较，验证最小化没有改变识别的语言
+        if let Err(err) = DFA::from(&self.nfa).equivalent(&self.dfa) {
+            panic!("not equivalent to the subset construction: {}", err);
+        }
+        println!("equivalent to the subset construction: true\n");
+        println!("regex of the dfa: {}\n", self.dfa.to_regex());
+
         *TEST_ID.lock().unwrap() += 1;
     }
 }
 
+/// 生成被接受和不被接受的串各若干个：按 shortlex 顺序最短的 3 个，以及随机的 1 个较长的串
+///
+/// 只使用每个区间等价类中的代表字符，不被接受的串为这些字符组成的串中不被 dfa 接受的
+fn examples(dfa: &DFA) -> Vec<String> {
+    const MAX_LEN: usize = 12;
+    let visible = dfa
+        .graph
+        .get_terminal()
+        .iter()
+        .map(|range| CharRange::single(range.representative()))
+        .collect();
+    let all = DFA::from_derivatives(&Regex::Star(Box::new(Regex::Class(normalize(visible)))));
+    let accepted = all.intersect(dfa);
+    let rejected = all.difference(dfa);
+
+    // 固定种子，每次运行生成的串相同
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut result = Vec::new();
+    for dfa in [&accepted, &rejected] {
+        result.extend(dfa.enumerate(MAX_LEN).take(3));
+        if let Some(len) = (0..=MAX_LEN).rev().find(|&len| dfa.count(len) > 0) {
+            result.extend(dfa.sample(len, &mut rng));
+        }
+    }
+    let mut seen = HashSet::new();
+    result.retain(|exp| seen.insert(exp.clone()));
+    result
+}
+
 fn test1() {
     let regular_expression = "(a(ab|c))*d*";
-    let expression = vec![
-        "aabacacaabddd",
-        "aabacacabdd",
-        "ddddddd",
-        "aaaaaaaaaa",
-        "",
-        "hello world",
-    ];
 
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
     test.run();
     let mut test = test;
     test.dfa.minimize();
@@ -83,15 +154,7 @@ fn test1() {
 
 fn test2() {
     let regular_expression = "woc*";
-    let expression = vec![
-        "wocccccc",
-        "woc",
-        "wo",
-        "woccc",
-        "wocccccc",
-        "woccccccwocccccc",
-    ];
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
     test.run();
     let mut test = test;
     test.dfa.minimize();
@@ -100,8 +163,7 @@ fn test2() {
 
 fn test3() {
     let regular_expression = "((ab)*|aaa)";
-    let expression = vec!["", "aaa", "ab", "abababa", "abababab"];
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
     test.run();
     let mut test = test;
     test.dfa.minimize();
@@ -110,24 +172,410 @@ fn test3() {
 
 fn test4() {
     let regular_expression = "b(a|b)*bab";
-    let expression = vec![
-        "",
-        "bbbbbbbbbbbbbbbbbbbbbbbbab",
-        "ab",
-        "bbab",
-        "babab",
-        "bab",
-    ];
-    let test = Test::from(regular_expression.to_string(), expression);
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+    let mut test = test;
+    test.dfa.minimize();
+    test.run();
+}
+
+fn test5() {
+    let regular_expression = r"-?[0-9]+(\.[0-9]+)?";
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+    let mut test = test;
+    test.dfa.minimize();
+    test.run();
+}
+
+fn test6() {
+    let regular_expression = r"[a-zA-Z_]\w*|\*+|[^a-z ]?";
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+    let mut test = test;
+    test.dfa.minimize();
+    test.run();
+}
+
+fn test7() {
+    let regular_expression = "0x[0-9a-f]{1,8}|a{2,}b{3}";
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+    let mut test = test;
+    test.dfa.minimize();
+    test.run();
+}
+
+fn test8() {
+    for regular_expression in [
+        "a|",
+        "(ab",
+        "*a",
+        "[z-a]",
+        "a{3,1}",
+        "((a{1000}){1000}){1000}",
+        "a{18446744073709551615}",
+        "a{18446744073709551615,}",
+    ] {
+        match NFA::try_from(regular_expression) {
+            Ok(_) => println!("regular expression: {} is valid\n", regular_expression),
+            Err(err) => println!("{}\n", err),
+        }
+    }
+}
+
+fn test9() {
+    let regular_expression = r"(\d{4})-(\d\d)-(\d\d) (INFO|WARN|ERROR) (\w+)=(\w*)";
+    let log = "2024-10-31 WARN user=alice\n2024-11-01 ERROR code=\n2024-11-02 INFO";
+    let nfa = NFA::from(regular_expression);
+    println!("regular expression: {}\n", regular_expression);
+    for range in nfa.find_iter(log) {
+        println!("found: {:?} {:?}", range, &log[range.clone()]);
+        let captures = nfa.captures(&log[range.clone()]).unwrap();
+        for (i, group) in captures.iter().enumerate().skip(1) {
+            match group {
+                Some(group) => println!("  group {}: {:?}", i, &log[range.start..][group.clone()]),
+                None => println!("  group {}: not matched", i),
+            }
+        }
+    }
+    println!("first match: {:?}\n", nfa.find(log));
+}
+
+fn test10() {
+    let identifier = DFA::from(&NFA::from(r"[a-zA-Z_]\w*"));
+    let number = DFA::from(&NFA::from(r"0x[0-9a-fA-F]+|[0-9]+"));
+    let operator = DFA::from(&NFA::from(r"[-+*/%=<>!]=?|&&|\|\||[(){};,]"));
+    let code = "int main(){ return 0x1f+a1>=b2; }";
+    println!("code: {}\n", code);
+
+    // 最长匹配：每个位置取所有自动机中最长的前缀，相同长度时取靠前的自动机
+    let mut start = 0;
+    while start < code.len() {
+        if code[start..].starts_with(' ') {
+            start += 1;
+            continue;
+        }
+        let mut best: Option<(usize, &str)> = None;
+        for (name, dfa) in [
+            ("Ident", &identifier),
+            ("Number", &number),
+            ("Operator", &operator),
+        ] {
+            if let Some(end) = dfa.longest_prefix(code, start) {
+                if end > start && best.is_none_or(|(best_end, _)| end > best_end) {
+                    best = Some((end, name));
+                }
+            }
+        }
+        match best {
+            Some((end, name)) => {
+                println!("{:<8} {:?}", name, &code[start..end]);
+                start = end;
+            }
+            None => {
+                println!("unknown character at {}", start);
+                start += code[start..].chars().next().unwrap().len_utf8();
+            }
+        }
+    }
+    println!();
+}
+
+fn test11() {
+    let mut lexer = LexerSpec::from(&[
+        ("Int", "int"),
+        ("Void", "void"),
+        ("Const", "const"),
+        ("If", "if"),
+        ("Else", "else"),
+        ("While", "while"),
+        ("Break", "break"),
+        ("Continue", "continue"),
+        ("Return", "return"),
+        ("Ident", r"[a-zA-Z_]\w*"),
+        ("Number", r"0[xX][0-9a-fA-F]+|0[0-7]*|[1-9][0-9]*"),
+        ("Operator", r"[-+*/%=<>!]=?|&&|\|\|"),
+        ("Delimiter", r"[(){}\[\];,]"),
+        ("Comment", r"//[^\n]*|/\*([^*]|\*+[^*/])*\*+/"),
+        ("WhiteSpace", r"\s+"),
+    ]);
+    let code = "int main() {\n    // 求和\n    const int a = 0x1f;\n    while (a >= 010) { a = a - 1; } /* done */\n    return a @ 2;\n}";
+    println!("code:\n{}\n", code);
+    for (name, range) in lexer.tokenize(code) {
+        if name != "WhiteSpace" {
+            println!("{:<10} {:?}", name, &code[range]);
+        }
+    }
+    println!();
+
+    // 生成独立的词法分析器源码
+    lexer.minimize();
+    let path = std::path::Path::new("result_code");
+    std::fs::create_dir_all(path).expect("Unable to create directory");
+    std::fs::write(path.join("sysy_lexer.rs"), lexer.generate_rust())
+        .expect("Unable to write file");
+    println!("generated lexer: result_code/sysy_lexer.rs\n");
+}
+
+fn test12() {
+    let regular_expression = r"[\u{4e00}-\u{9fa5}a-zA-Z_][\u{4e00}-\u{9fa5}\w]*";
+    let test = Test::from(regular_expression.to_string());
     test.run();
     let mut test = test;
     test.dfa.minimize();
     test.run();
+
+    // \p{L} 匹配所有 Unicode 字母，\p{N} 匹配所有 Unicode 数字
+    let nfa = NFA::from(r"\p{L}[\p{L}\p{N}]*");
+    let dfa = DFA::from(&nfa);
+    println!(
+        "dfa states for \\p{{L}}[\\p{{L}}\\p{{N}}]*: {}",
+        dfa.graph.edges.len()
+    );
+    for exp in ["αβγ", "Ωmega२", "привет", "9lives", "日本語"] {
+        println!(
+            "{:?}: nfa {}, dfa {}",
+            exp,
+            nfa.contains(exp),
+            dfa.contains(exp)
+        );
+    }
+    println!();
+}
+
+fn test13() {
+    // ε 边使用 `Label::Epsilon` 表示，字符 ε 可以像普通字符一样匹配
+    let regular_expression = "aε*b|ε";
+    let test = Test::from(regular_expression.to_string());
+    test.run();
+    let mut test = test;
+    test.dfa.minimize();
+    test.run();
+}
+
+fn test14() {
+    let identifier = DFA::from(&NFA::from(r"[a-zA-Z_]\w*"));
+    let mut complete = identifier.clone();
+    complete.complete();
+    println!(
+        "identifier dfa: {} states, completed: {} states",
+        identifier.graph.edges.len(),
+        complete.graph.edges.len()
+    );
+    let mut not_identifier = identifier.complement();
+    not_identifier.minimize();
+    for exp in ["", "main", "_x1", "1abc", "a-b", "变量"] {
+        println!(
+            "{:?}: identifier {}, not identifier {}",
+            exp,
+            identifier.contains(exp),
+            not_identifier.contains(exp)
+        );
+    }
+    println!();
+}
+
+fn test15() {
+    let identifier = DFA::from(&NFA::from(r"[a-zA-Z_]\w*"));
+    let keyword = DFA::from(&NFA::from("int|void|if|else|while|return"));
+    let number = DFA::from(&NFA::from("[0-9]+"));
+    println!(
+        "Ident and keyword overlap: {}",
+        !identifier.intersect(&keyword).is_empty()
+    );
+    println!(
+        "Ident and Number overlap: {}",
+        !identifier.intersect(&number).is_empty()
+    );
+
+    // 不是关键字的标识符
+    let name = identifier.difference(&keyword);
+    let word = identifier.union(&number);
+    for exp in ["int", "integer", "if", "_if", "42", "4a"] {
+        println!(
+            "{:?}: Ident but not keyword {}, Ident or Number {}",
+            exp,
+            name.contains(exp),
+            word.contains(exp)
+        );
+    }
+
+    // 关键字都是标识符，反过来不成立
+    println!("keyword ⊆ Ident: {:?}", keyword.is_subset_of(&identifier));
+    println!("Ident ⊆ keyword: {:?}", identifier.is_subset_of(&keyword));
+    println!();
+}
+
+fn test16() {
+    // NFA -> 正规表达式 -> NFA，两次得到的DFA应当等价
+    for regular_expression in [
+        "(a|b)*abb",
+        r"-?[0-9]+(\.[0-9]+)?",
+        r"//[^\n]*|/\*([^*]|\*+[^*/])*\*+/",
+    ] {
+        let nfa = NFA::from(regular_expression);
+        let regex = nfa.to_regex();
+        let mut dfa = DFA::from(&nfa);
+        dfa.minimize();
+        println!("regular expression: {}", regular_expression);
+        println!("regex of the nfa: {}", regex);
+        println!("regex of the minimized dfa: {}", dfa.to_regex());
+        let round_trip = DFA::from(&NFA::from(&regex.to_string()));
+        println!("round trip: {:?}\n", round_trip.equivalent(&dfa));
+    }
+}
+
+fn test17() {
+    // 用导数构造的DFA与子集构造的DFA互相验证
+    for regular_expression in [
+        "(a|b)*abb",
+        r"-?[0-9]+(\.[0-9]+)?",
+        r"[a-zA-Z_]\w*|\*+|[^a-z ]?",
+        "0x[0-9a-f]{1,8}|a{2,}b{3}",
+        r"//[^\n]*|/\*([^*]|\*+[^*/])*\*+/",
+    ] {
+        let regex = Regex::parse(regular_expression).unwrap();
+        let by_derivatives = DFA::from_derivatives(&regex);
+        let by_subsets = DFA::from(&NFA::from(regular_expression));
+        println!("regular expression: {}", regular_expression);
+        println!(
+            "states: derivatives {}, subset construction {}",
+            by_derivatives.graph.edges.len(),
+            by_subsets.graph.edges.len()
+        );
+        let equivalent = by_derivatives.equivalent(&by_subsets);
+        println!("equivalent: {:?}", equivalent);
+        assert_eq!(equivalent, Ok(()), "{}", regular_expression);
+        for exp in ["abb", "-3.14", "_x1", "0xff", "/* a */"] {
+            let (by_derivative, by_dfa) =
+                (derivative::is_match(&regex, exp), by_subsets.contains(exp));
+            println!("{:?}: derivatives {}, dfa {}", exp, by_derivative, by_dfa);
+            assert_eq!(by_derivative, by_dfa, "{} on {:?}", regular_expression, exp);
+        }
+        println!();
+    }
+}
+
+fn test18() {
+    // 完整的子集构造会产生 2^21 个状态，按需构造只生成匹配时走到的状态
+    let nfa = NFA::from("(a|b)*a(a|b){20}");
+    // Thue-Morse 序列不是周期的，匹配时会走到很多不同的状态子集；倒数第 21 个字符为 `a`，因此被接受
+    let text: String = (0..201u32)
+        .map(|i| if i.count_ones() % 2 == 0 { 'a' } else { 'b' })
+        .collect();
+    let mut lazy = LazyDFA::from(&nfa);
+    println!(
+        "lazy dfa: {}, nfa: {}",
+        lazy.contains(&text),
+        nfa.contains(&text)
+    );
+    println!("cached states: {}", lazy.cached_states());
+
+    // 缓存很小时会反复清空，最终退化为NFA模拟
+    let mut small = LazyDFA::with_capacity(&nfa, 16);
+    println!("lazy dfa with 16 states: {}", small.contains(&text));
+    println!(
+        "cache clears: {}, fallbacks: {}\n",
+        small.cache_clears, small.fallbacks
+    );
+}
+
+fn test19() {
+    // 保存构造好的自动机，之后可以直接加载或与其他版本的输出比较
+    let nfa = NFA::from(r"(\d+)(?:\.(\d+))?|'ε'");
+    let mut dfa = DFA::from(&nfa);
+    dfa.minimize();
+    let path = std::path::Path::new("result_automata");
+    std::fs::create_dir_all(path).expect("Unable to create directory");
+    let nfa_json = nfa.to_automaton().to_json();
+    let dfa_text = dfa.to_automaton().to_text();
+    std::fs::write(path.join("nfa.json"), &nfa_json).expect("Unable to write file");
+    std::fs::write(path.join("dfa.txt"), &dfa_text).expect("Unable to write file");
+    println!("dfa table:\n{}", dfa_text);
+
+    let loaded_nfa = Automaton::from_json(&nfa_json).and_then(|a| NFA::from_automaton(&a));
+    let loaded_dfa = Automaton::from_text(&dfa_text).and_then(|a| DFA::from_automaton(&a));
+    match (loaded_nfa, loaded_dfa) {
+        (Ok(loaded_nfa), Ok(loaded_dfa)) => {
+            println!("loaded nfa captures: {:?}", loaded_nfa.captures("3.14"));
+            println!(
+                "loaded dfa equivalent: {:?}",
+                loaded_dfa.equivalent(&DFA::from(&loaded_nfa))
+            );
+        }
+        (Err(err), _) | (_, Err(err)) => println!("failed to load: {}", err),
+    }
+    for text in [
+        "kind dfa\nstates 1\nstart 2",
+        "kind dfa\nstates 2\nedge 0 1 'a'-",
+    ] {
+        match Automaton::from_text(text).and_then(|a| DFA::from_automaton(&a)) {
+            Ok(_) => println!("loaded"),
+            Err(err) => println!("error: {}", err),
+        }
+    }
+    println!();
 }
 
-fn main() {
+/// 把 `PICTURE_DIR` 中的 DOT 文件渲染为图片，没有安装 Graphviz 时只打印提示
+fn render_pictures() {
+    let entries = match std::fs::read_dir(PICTURE_DIR) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("failed to read {}: {}", PICTURE_DIR, err);
+            return;
+        }
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dot"))
+        .collect();
+    files.sort();
+    for file in files {
+        match render_dot(&file, "png") {
+            Ok(png) => println!("rendered {}", png.display()),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        }
+    }
+}
+
+fn run_tests() {
     test1();
     test2();
     test3();
     test4();
+    test5();
+    test6();
+    test7();
+    test8();
+    test9();
+    test10();
+    test11();
+    test12();
+    test13();
+    test14();
+    test15();
+    test16();
+    test17();
+    test18();
+    test19();
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        // 运行全部测试，需要图片时使用 `cargo run -- test --render`
+        Some("test") if args[1..].iter().all(|arg| arg == "--render") => {
+            run_tests();
+            if args.len() > 1 {
+                render_pictures();
+            }
+            ExitCode::SUCCESS
+        }
+        _ => cli::run(&args),
+    }
 }
@@ -1,8 +1,14 @@
-use crate::graph::Graph_;
-use core::panic;
+use crate::charset::CharRange;
+use crate::graph::{Graph_, Label, Slots};
+use crate::regex::{Regex, RegexError};
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::fmt::Display;
 use std::fmt::Formatter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
+#[derive(Clone)]
 pub struct NFA {
     /// 确保起点为0，终点为 len - 1
     pub graph: Graph_,
@@ -14,159 +20,141 @@ impl Debug for NFA {
     }
 }
 
+/// 状态转移表，`→` 标记开始状态，`*` 标记接受状态，每一格为可以到达的状态集合
+impl Display for NFA {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let end = self.graph.edges.len() - 1;
+        write!(f, "{}", self.graph.transition_table(0, &[end], &[]))
+    }
+}
+
+impl TryFrom<&str> for NFA {
+    type Error = RegexError;
+
+    fn try_from(exp: &str) -> Result<Self, Self::Error> {
+        Ok(NFA::from_ast(&Regex::parse(exp)?.simplify()))
+    }
+}
+
 impl NFA {
+    /// 表达式不合法时 panic 并打印错误位置，需要处理错误时使用 `NFA::try_from`
     pub fn from(exp: &str) -> NFA {
-        let transfer_exp = add_connect(exp);
-        // println!("transferd expression is {}", transfer_exp);
-        let mut nfa_stack = Vec::new();
-        let mut op_stack = Vec::new();
-        // ( ) > * > + > |
-        for c in transfer_exp.chars() {
-            match c {
-                '(' => {
-                    op_stack.push(c);
-                }
-                ')' => {
-                    while let Some(op) = op_stack.pop() {
-                        if op == '(' {
-                            break;
-                        }
-                        let b = nfa_stack.pop().unwrap();
-                        let a = nfa_stack.pop().unwrap();
-                        match op {
-                            '*' => {
-                                nfa_stack.push(repeat(&a));
-                            }
-                            '|' => {
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '+' => {
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
-                        }
-                    }
-                }
-                '*' => {
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(repeat(&a));
-                }
-                '+' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
-                        }
-                    }
-                    op_stack.push(c);
-                }
-                '|' => {
-                    while let Some(op) = op_stack.pop() {
-                        match op {
-                            '*' => {
-                                panic!("error: * can't be here");
-                            }
-                            '+' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(and(&a, &b));
-                            }
-                            '|' => {
-                                let b = nfa_stack.pop().unwrap();
-                                let a = nfa_stack.pop().unwrap();
-                                nfa_stack.push(or(&a, &b));
-                            }
-                            '(' => {
-                                op_stack.push(op);
-                                break;
-                            }
-                            _ => {
-                                panic!("error: unknown operator {}", op);
-                            }
-                        }
-                    }
-                    op_stack.push(c);
-                }
-                _ => {
-                    assert!(c.is_alphanumeric());
-                    nfa_stack.push(teminate(c));
-                }
-            }
-            // println!("============ c is: {} ===============", c);
-            // println!("nfa_stack: {:?}", nfa_stack);
-            // println!("op_stack: {:?}", op_stack);
-            // println!("");
-        }
+        NFA::try_from(exp).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        while let Some(op) = op_stack.pop() {
-            match op {
-                '*' => {
-                    panic!("error: * can't be here");
-                }
-                '+' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(and(&a, &b));
-                }
-                '|' => {
-                    let b = nfa_stack.pop().unwrap();
-                    let a = nfa_stack.pop().unwrap();
-                    nfa_stack.push(or(&a, &b));
-                }
-                '(' => {
-                    panic!("error: ( can't be here");
-                }
-                ')' => {
-                    panic!("error: ) can't be here");
-                }
-                _ => {
-                    panic!("error: unknown operator {}", op);
-                }
+    /// 对语法树进行 Thompson 构造
+    pub fn from_ast(regex: &Regex) -> NFA {
+        match regex {
+            Regex::Empty => empty(),
+            Regex::Char(c) => teminate(Label::Char(*c)),
+            Regex::Class(ranges) => class(ranges),
+            Regex::Concat(items) => items
+                .iter()
+                .map(NFA::from_ast)
+                .reduce(|result, item| and(&result, &item))
+                .unwrap_or_else(empty),
+            Regex::Alt(items) => items
+                .iter()
+                .map(NFA::from_ast)
+                .reduce(|result, item| or(&result, &item))
+                .unwrap_or_else(empty),
+            Regex::Star(a) => repeat(&NFA::from_ast(a)),
+            Regex::Plus(a) => {
+                let a = NFA::from_ast(a);
+                and(&a, &repeat(&a))
             }
+            Regex::Opt(a) => or(&NFA::from_ast(a), &empty()),
+            Regex::Repeat(a, min, max) => repeat_range(&NFA::from_ast(a), *min, *max),
+            Regex::Group(index, a) => group(&NFA::from_ast(a), *index),
         }
+    }
 
-        assert_eq!(nfa_stack.len(), 1);
-        assert_eq!(op_stack.len(), 0);
-        nfa_stack.pop().unwrap()
+    /// 把 DOT 源码写入 dir 目录下的 `nfa_{id}.dot`
+    pub fn show(&self, dir: &Path, id: usize) -> Result<PathBuf, String> {
+        self.graph.show_graph(
+            dir,
+            &format!("nfa_{}.dot", id),
+            0,
+            &[self.graph.edges.len() - 1],
+        )
     }
 
-    pub fn show(&self, id: usize) {
-        self.graph
-            .show_graph(&format!("nfa_{}.dot", id), 0, &[self.graph.edges.len() - 1]);
+    /// 用状态消去法转换为正规表达式，捕获组被忽略
+    pub fn to_regex(&self) -> Regex {
+        self.graph.to_regex(0, &[self.graph.edges.len() - 1])
     }
 
     pub fn contains(&self, exp: &str) -> bool {
-        let mut succeed = false;
+        self.graph.simulate(0, &[self.graph.edges.len() - 1], exp)
+    }
+
+    /// 捕获组的个数，不含整个匹配
+    pub fn groups(&self) -> usize {
+        self.graph.tags.values().max().map_or(0, |slot| slot / 2)
+    }
+
+    fn search(&self, text: &str, start: usize) -> Option<Slots> {
+        let slot_count = 2 * (self.groups() + 1);
         self.graph
-            .dfs_search(0, &vec![self.graph.edges.len() - 1], 0, exp, &mut succeed)
+            .search(0, self.graph.edges.len() - 1, text, start, slot_count)
     }
-}
 
-fn offset(edge: &Vec<(usize, char)>, offset: usize) -> Vec<(usize, char)> {
-    let mut result = Vec::new();
-    for i in 0..edge.len() {
-        result.push((edge[i].0 + offset, edge[i].1));
+    /// 查找最左的匹配，返回其字节范围
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        let slots = self.search(text, 0)?;
+        Some(slots[0].unwrap()..slots[1].unwrap())
+    }
+
+    /// 依次查找所有互不重叠的匹配，空匹配之后跳过一个字符
+    ///
+    /// 与常见的正则引擎一致，紧接在上一个匹配末尾的空匹配不会被返回
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut start = 0;
+        let mut last_end = None;
+        std::iter::from_fn(move || loop {
+            if start > text.len() {
+                return None;
+            }
+            let slots = self.search(text, start)?;
+            let range = slots[0].unwrap()..slots[1].unwrap();
+            if range.is_empty() {
+                start = range.end + text[range.end..].chars().next().map_or(1, char::len_utf8);
+                if last_end == Some(range.start) {
+                    continue;
+                }
+            } else {
+                start = range.end;
+            }
+            last_end = Some(range.end);
+            return Some(range);
+        })
+    }
+
+    /// 查找最左的匹配，第 0 项为整个匹配，第 k 项为第 k 个捕获组，未参与匹配的组为 `None`
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.search(text, 0)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| match (pair[0], pair[1]) {
+                    (Some(start), Some(end)) => Some(start..end),
+                    _ => None,
+                })
+                .collect(),
+        )
     }
-    result
+}
+
+fn offset(edge: &[(usize, Label)], offset: usize) -> Vec<(usize, Label)> {
+    edge.iter()
+        .map(|&(to, label)| (to + offset, label))
+        .collect()
+}
+
+fn offset_tags(tags: &HashMap<usize, usize>, offset: usize) -> HashMap<usize, usize> {
+    tags.iter()
+        .map(|(&state, &slot)| (state + offset, slot))
+        .collect()
 }
 
 /// connect a and b
@@ -175,27 +163,32 @@ fn and(a: &NFA, b: &NFA) -> NFA {
     let b_edges = b.graph.edges.clone();
     let mut result = a_edges.clone();
 
-    for i in 0..b_edges.len() {
-        let mut tmp = Vec::new();
-        for j in 0..b_edges[i].len() {
-            tmp.push((b_edges[i][j].0 + a_edges.len(), b_edges[i][j].1));
-        }
-        result.push(tmp);
+    for edge in &b_edges {
+        result.push(offset(edge, a_edges.len()));
     }
-    result[a_edges.len() - 1].push((a_edges.len(), 'ε'));
+    result[a_edges.len() - 1].push((a_edges.len(), Label::Epsilon));
+
+    let mut tags = a.graph.tags.clone();
+    tags.extend(offset_tags(&b.graph.tags, a_edges.len()));
     NFA {
-        graph: Graph_ { edges: result },
+        graph: Graph_ {
+            edges: result,
+            tags,
+        },
     }
 }
 
 /// or a and b
-fn or(a: &NFA, b: &NFA) -> NFA {
+pub fn or(a: &NFA, b: &NFA) -> NFA {
     let a_edges = a.graph.edges.clone();
     let b_edges = b.graph.edges.clone();
     let mut result = Vec::new();
 
     // point 0
-    result.push(vec![(1, 'ε'), (a_edges.len() + 1, 'ε')]);
+    result.push(vec![
+        (1, Label::Epsilon),
+        (a_edges.len() + 1, Label::Epsilon),
+    ]);
 
     // point 1 to a_edges.len()
     for edge in &a_edges {
@@ -210,11 +203,16 @@ fn or(a: &NFA, b: &NFA) -> NFA {
     // point a_edges.len() + b_edges.len() + 1
     result.push(vec![]);
 
-    result[a_edges.len()].push((a_edges.len() + b_edges.len() + 1, 'ε'));
-    result[a_edges.len() + b_edges.len()].push((a_edges.len() + b_edges.len() + 1, 'ε'));
+    result[a_edges.len()].push((a_edges.len() + b_edges.len() + 1, Label::Epsilon));
+    result[a_edges.len() + b_edges.len()].push((a_edges.len() + b_edges.len() + 1, Label::Epsilon));
 
+    let mut tags = offset_tags(&a.graph.tags, 1);
+    tags.extend(offset_tags(&b.graph.tags, a_edges.len() + 1));
     NFA {
-        graph: Graph_ { edges: result },
+        graph: Graph_ {
+            edges: result,
+            tags,
+        },
     }
 }
 
@@ -224,7 +222,10 @@ fn repeat(a: &NFA) -> NFA {
     let mut result = Vec::new();
 
     // point 0
-    result.push(vec![(1, 'ε'), (a_edges.len() + 1, 'ε')]);
+    result.push(vec![
+        (1, Label::Epsilon),
+        (a_edges.len() + 1, Label::Epsilon),
+    ]);
 
     // point 1 to a_edges.len()
     for edge in &a_edges {
@@ -236,40 +237,165 @@ fn repeat(a: &NFA) -> NFA {
 
     assert_eq!(result.len(), a_edges.len() + 2);
 
-    result[a_edges.len()].push((1, 'ε'));
-    result[a_edges.len()].push((a_edges.len() + 1, 'ε'));
+    result[a_edges.len()].push((1, Label::Epsilon));
+    result[a_edges.len()].push((a_edges.len() + 1, Label::Epsilon));
 
     NFA {
-        graph: Graph_ { edges: result },
+        graph: Graph_ {
+            edges: result,
+            tags: offset_tags(&a.graph.tags, 1),
+        },
     }
 }
 
-/// teminate
-fn teminate(a: char) -> NFA {
+/// capture a as the group with the index
+fn group(a: &NFA, index: usize) -> NFA {
+    let a_edges = a.graph.edges.clone();
     let mut result = Vec::new();
-    result.push(vec![(1, a)]);
+
+    // point 0, 记录组的起始位置
+    result.push(vec![(1, Label::Epsilon)]);
+
+    // point 1 to a_edges.len()
+    for edge in &a_edges {
+        result.push(offset(edge, 1));
+    }
+
+    // point a_edges.len() + 1, 记录组的结束位置
     result.push(vec![]);
+
+    result[a_edges.len()].push((a_edges.len() + 1, Label::Epsilon));
+
+    let mut tags = offset_tags(&a.graph.tags, 1);
+    tags.insert(0, 2 * index);
+    tags.insert(a_edges.len() + 1, 2 * index + 1);
     NFA {
-        graph: Graph_ { edges: result },
+        graph: Graph_ {
+            edges: result,
+            tags,
+        },
+    }
+}
+
+/// repeat a at least min times and at most max times
+fn repeat_range(a: &NFA, min: usize, max: Option<usize>) -> NFA {
+    let mut parts = Vec::new();
+    for _ in 0..min {
+        parts.push(a.clone());
+    }
+    match max {
+        Some(max) => {
+            for _ in min..max {
+                parts.push(or(a, &empty()));
+            }
+        }
+        None => parts.push(repeat(a)),
+    }
+    parts
+        .into_iter()
+        .reduce(|result, part| and(&result, &part))
+        .unwrap_or_else(empty)
+}
+
+/// teminate
+fn teminate(a: Label) -> NFA {
+    let mut graph = Graph_::new(2);
+    graph.add_edge(0, 1, a);
+    NFA { graph }
+}
+
+/// match any char of the class
+fn class(ranges: &[CharRange]) -> NFA {
+    let mut graph = Graph_::new(2);
+    for &range in ranges {
+        graph.add_edge(0, 1, Label::from(range));
     }
+    NFA { graph }
 }
 
-/// transfer the raw expression
-fn add_connect(exp: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = exp.chars().collect();
-    let len = chars.len();
-    for i in 0..len {
-        result.push(chars[i]);
-        if i + 1 < len {
-            let current = chars[i];
-            let next = chars[i + 1];
-            if (current.is_alphanumeric() || current == ')' || current == '*')
-                && (next.is_alphanumeric() || next == '(')
-            {
-                result.push('+');
+/// match the empty string
+fn empty() -> NFA {
+    teminate(Label::Epsilon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternatives_keep_their_priority() {
+        let nfa = NFA::from("(a|xy|x)");
+        assert_eq!(nfa.captures("xy"), Some(vec![Some(0..2), Some(0..2)]));
+        assert_eq!(NFA::from("a|xy|x").find("xy"), Some(0..2));
+        assert_eq!(NFA::from("xy|x|a").find("xy"), Some(0..2));
+        assert_eq!(
+            NFA::from("((?:)|a)").captures("a"),
+            Some(vec![Some(0..0), Some(0..0)])
+        );
+        assert_eq!(
+            NFA::from("(a|(?:))").captures("a"),
+            Some(vec![Some(0..1), Some(0..1)])
+        );
+    }
+
+    #[test]
+    fn simplify_does_not_change_captures() {
+        let patterns = [
+            "(a|xy|x)",
+            "(x|a|xy)(y?)",
+            "((?:)|a)(a*)",
+            "(a|(?:)|b)(a*)",
+            "(a|b|ab)(b*)",
+            "((?:a|b)|ab|c)(.*)",
+            "(a*)*(b)",
+            r"(\d|\d\d)(\d*)",
+        ];
+        let texts = ["xy", "axy", "aab", "abb", "cab", "b", "123", ""];
+        for pattern in patterns {
+            let simplified = NFA::from(pattern);
+            let original = NFA::from_ast(&Regex::parse(pattern).unwrap());
+            for text in texts {
+                assert_eq!(
+                    simplified.captures(text),
+                    original.captures(text),
+                    "{} on {:?}",
+                    pattern,
+                    text
+                );
             }
         }
     }
-    result
+
+    #[test]
+    fn epsilon_column_header_is_unique() {
+        let nfa = NFA::from("aε*b|ε");
+        let table = nfa.to_string();
+        let header: Vec<&str> = table
+            .lines()
+            .next()
+            .unwrap()
+            .split(" | ")
+            .map(str::trim)
+            .collect();
+        let mut unique = header.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(header.len(), unique.len());
+        assert!(header.contains(&"(ε)"));
+    }
+
+    #[test]
+    fn find_iter_skips_empty_matches_after_a_match() {
+        let matches = |pattern: &str, text: &str| -> Vec<(usize, usize)> {
+            NFA::from(pattern)
+                .find_iter(text)
+                .map(|range| (range.start, range.end))
+                .collect()
+        };
+        assert_eq!(matches("(a|b)*", "ab"), [(0, 2)]);
+        assert_eq!(matches("a*", "baaa"), [(0, 0), (1, 4)]);
+        assert_eq!(matches("a*", "aab"), [(0, 2), (3, 3)]);
+        assert_eq!(matches("(a*)+", "b"), [(0, 0), (1, 1)]);
+        assert_eq!(matches("a*|b", "abc"), [(0, 1), (2, 2), (3, 3)]);
+    }
 }
@@ -0,0 +1,836 @@
+use crate::charset::{complement, normalize, CharRange};
+use std::fmt::{Display, Formatter};
+
+/// 正规表达式的词法单元
+///
+/// 连接运算使用单独的 `Concat` 表示，不会与用户输入的 `+` 冲突
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// 单个字符、字符类或 `.`，已经展开为规范化的区间
+    Chars(Vec<CharRange>),
+    /// `(` 为捕获组，`(?:` 为非捕获组
+    LParen(bool),
+    RParen,
+    Alt,
+    Concat,
+    Star,
+    Plus,
+    Optional,
+    /// `{m}`、`{m,}`、`{m,n}`，上界为 `None` 表示不限次数
+    Repeat(usize, Option<usize>),
+}
+
+/// `{m,n}` 最多允许展开的副本数
+pub const DEFAULT_REPEAT_LIMIT: usize = 1000;
+
+/// 正规表达式的语法树
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Regex {
+    /// 空串
+    Empty,
+    Char(char),
+    /// 字符集合，保持为有序、互不相邻的区间
+    Class(Vec<CharRange>),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Opt(Box<Regex>),
+    /// `{m}`、`{m,}`、`{m,n}`，上界为 `None` 表示不限次数
+    Repeat(Box<Regex>, usize, Option<usize>),
+    /// 捕获组，编号从 1 开始，按左括号出现的顺序分配
+    Group(usize, Box<Regex>),
+}
+
+/// 正规表达式的语法错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    pub pattern: String,
+    /// 出错位置在 `pattern` 中的字节偏移
+    pub offset: usize,
+    pub message: String,
+    /// 该位置期望出现的内容
+    pub expected: String,
+}
+
+impl RegexError {
+    pub fn new(pattern: &str, offset: usize, message: &str, expected: &str) -> Self {
+        RegexError {
+            pattern: pattern.to_string(),
+            offset,
+            message: message.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let column = self.pattern[..self.offset].chars().count();
+        writeln!(f, "regex error: {}", self.message)?;
+        writeln!(f, "  --> offset {}", self.offset)?;
+        writeln!(f, "   |")?;
+        writeln!(f, "   | {}", self.pattern)?;
+        writeln!(f, "   | {}^ expected {}", " ".repeat(column), self.expected)?;
+        write!(f, "   |")
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// `.` 所匹配的字符集合，即除换行以外的所有字符
+fn dot() -> Vec<CharRange> {
+    complement(&[CharRange::single('\n')])
+}
+
+/// 满足条件的所有字符，用于 `\p{L}` 这类 Unicode 字符类
+fn chars_where(predicate: fn(char) -> bool) -> Vec<CharRange> {
+    let mut result = Vec::new();
+    let mut start: Option<char> = None;
+    let mut prev = '\0';
+    for c in '\0'..=char::MAX {
+        if predicate(c) {
+            if start.is_none() {
+                start = Some(c);
+            }
+        } else if let Some(s) = start.take() {
+            result.push(CharRange::new(s, prev));
+        }
+        prev = c;
+    }
+    if let Some(s) = start {
+        result.push(CharRange::new(s, prev));
+    }
+    normalize(result)
+}
+
+/// 逐字符读取正规表达式，并记录每个字符的字节偏移
+struct Scanner<'a> {
+    pattern: &'a str,
+    chars: Vec<(usize, char)>,
+    index: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(pattern: &'a str) -> Self {
+        Scanner {
+            pattern,
+            chars: pattern.char_indices().collect(),
+            index: 0,
+        }
+    }
+
+    /// 当前字符的字节偏移，读完时为表达式长度
+    fn offset(&self) -> usize {
+        self.chars
+            .get(self.index)
+            .map_or(self.pattern.len(), |&(offset, _)| offset)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).map(|&(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.index += 1;
+        }
+        c
+    }
+
+    fn error(&self, offset: usize, message: &str, expected: &str) -> RegexError {
+        RegexError::new(self.pattern, offset, message, expected)
+    }
+
+    /// 解析 `\` 之后的转义序列
+    fn parse_escape(&mut self) -> Result<Vec<CharRange>, RegexError> {
+        let offset = self.offset();
+        let c = self.next().ok_or_else(|| {
+            self.error(offset, "dangling escape at the end", "an escaped character")
+        })?;
+        let digit = vec![CharRange::new('0', '9')];
+        let word = vec![
+            CharRange::new('0', '9'),
+            CharRange::new('A', 'Z'),
+            CharRange::single('_'),
+            CharRange::new('a', 'z'),
+        ];
+        let space = normalize(" \t\n\r".chars().map(CharRange::single).collect());
+        Ok(match c {
+            'n' => vec![CharRange::single('\n')],
+            't' => vec![CharRange::single('\t')],
+            'r' => vec![CharRange::single('\r')],
+            'd' => digit,
+            'w' => word,
+            's' => space,
+            'D' => complement(&digit),
+            'W' => complement(&word),
+            'S' => complement(&space),
+            'u' => {
+                let expected = "`{`, a hexadecimal code point and `}`";
+                let c = self.parse_braced(offset, expected, |name| {
+                    u32::from_str_radix(name, 16).ok().and_then(char::from_u32)
+                })?;
+                vec![CharRange::single(c)]
+            }
+            'p' => self.parse_braced(offset, "`{L}` or `{N}`", |name| match name {
+                "L" => Some(chars_where(char::is_alphabetic)),
+                "N" => Some(chars_where(char::is_numeric)),
+                _ => None,
+            })?,
+            _ => vec![CharRange::single(c)],
+        })
+    }
+
+    /// 解析 `\u{...}`、`\p{...}` 中花括号内的内容，offset 为转义字母的位置
+    fn parse_braced<T>(
+        &mut self,
+        offset: usize,
+        expected: &str,
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Result<T, RegexError> {
+        if self.next() != Some('{') {
+            return Err(self.error(offset, "invalid escape", expected));
+        }
+        let begin = self.offset();
+        while self.peek().is_some_and(|c| c != '}') {
+            self.index += 1;
+        }
+        let name = &self.pattern[begin..self.offset()];
+        if self.next() != Some('}') {
+            return Err(self.error(self.offset(), "unclosed escape", "`}`"));
+        }
+        parse(name).ok_or_else(|| self.error(begin, "invalid escape", expected))
+    }
+
+    /// 读取一个十进制整数，没有数字时返回 `None`
+    fn parse_number(&mut self) -> Result<Option<usize>, RegexError> {
+        let begin = self.offset();
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.index += 1;
+        }
+        let digits = &self.pattern[begin..self.offset()];
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        digits
+            .parse()
+            .map(Some)
+            .map_err(|_| self.error(begin, "repetition count is too large", "a smaller number"))
+    }
+
+    /// 解析 `{m}`、`{m,}`、`{m,n}`，`{` 已经被读取
+    fn parse_repeat(&mut self) -> Result<Token, RegexError> {
+        let min = self
+            .parse_number()?
+            .ok_or_else(|| self.error(self.offset(), "invalid repetition", "a number"))?;
+        let max = if self.peek() == Some(',') {
+            self.index += 1;
+            self.parse_number()?
+        } else {
+            Some(min)
+        };
+        if self.peek() != Some('}') {
+            return Err(self.error(self.offset(), "unclosed repetition", "`}`"));
+        }
+        self.index += 1;
+        if let Some(max) = max {
+            if min > max {
+                return Err(self.error(
+                    self.offset() - 1,
+                    &format!("invalid repetition {{{},{}}}", min, max),
+                    &format!("an upper bound of at least {}", min),
+                ));
+            }
+        }
+        Ok(Token::Repeat(min, max))
+    }
+
+    /// 解析 `[...]` 字符类，`[` 已经被读取
+    fn parse_class(&mut self) -> Result<Vec<CharRange>, RegexError> {
+        let negate = self.peek() == Some('^');
+        if negate {
+            self.index += 1;
+        }
+
+        let mut set = Vec::new();
+        let mut first = true;
+        loop {
+            let offset = self.offset();
+            let c = self
+                .next()
+                .ok_or_else(|| self.error(offset, "unclosed character class", "`]`"))?;
+            // 第一个字符为 `]` 时视为普通字符
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let low = if c == '\\' {
+                let escaped = self.parse_escape()?;
+                match escaped.as_slice() {
+                    [range] if range.start == range.end => range.start,
+                    _ => {
+                        set.extend(escaped);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            let is_range = self.peek() == Some('-')
+                && self
+                    .chars
+                    .get(self.index + 1)
+                    .is_some_and(|&(_, c)| c != ']');
+            if is_range {
+                self.index += 1;
+                let high_offset = self.offset();
+                let mut high = self.next().unwrap();
+                if high == '\\' {
+                    // `\d` 这类表示多个字符的转义不能作为区间的端点
+                    high = match self.parse_escape()?.as_slice() {
+                        [range] if range.start == range.end => range.start,
+                        _ => {
+                            return Err(self.error(
+                                high_offset,
+                                "invalid range end",
+                                "a single character",
+                            ))
+                        }
+                    };
+                }
+                if low > high {
+                    return Err(self.error(
+                        high_offset,
+                        &format!("invalid range {}-{}", low, high),
+                        &format!("a character not less than `{}`", low),
+                    ));
+                }
+                set.push(CharRange::new(low, high));
+            } else {
+                set.push(CharRange::single(low));
+            }
+        }
+
+        let set = normalize(set);
+        Ok(if negate { complement(&set) } else { set })
+    }
+}
+
+/// 将原始的正规表达式切分为词法单元，每个单元附带其字节偏移
+pub fn tokenize(exp: &str) -> Result<Vec<(usize, Token)>, RegexError> {
+    let mut scanner = Scanner::new(exp);
+    let mut result = Vec::new();
+    while let Some(c) = scanner.peek() {
+        let offset = scanner.offset();
+        scanner.index += 1;
+        let token = match c {
+            '(' => {
+                let non_capturing = scanner.peek() == Some('?')
+                    && scanner.chars.get(scanner.index + 1).map(|&(_, c)| c) == Some(':');
+                if non_capturing {
+                    scanner.index += 2;
+                }
+                Token::LParen(!non_capturing)
+            }
+            ')' => Token::RParen,
+            '|' => Token::Alt,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '?' => Token::Optional,
+            '.' => Token::Chars(dot()),
+            '[' => Token::Chars(scanner.parse_class()?),
+            '{' => scanner.parse_repeat()?,
+            '\\' => Token::Chars(scanner.parse_escape()?),
+            _ => Token::Chars(vec![CharRange::single(c)]),
+        };
+        result.push((offset, token));
+    }
+    Ok(result)
+}
+
+/// 在需要连接的两个词法单元之间插入 `Concat`
+pub fn add_connect(tokens: Vec<(usize, Token)>) -> Vec<(usize, Token)> {
+    let mut result: Vec<(usize, Token)> = Vec::new();
+    for (offset, token) in tokens {
+        if let Some((_, prev)) = result.last() {
+            let left = matches!(
+                prev,
+                Token::Chars(_)
+                    | Token::RParen
+                    | Token::Star
+                    | Token::Plus
+                    | Token::Optional
+                    | Token::Repeat(..)
+            );
+            let right = matches!(token, Token::Chars(_) | Token::LParen(_));
+            if left && right {
+                result.push((offset, Token::Concat));
+            }
+        }
+        result.push((offset, token));
+    }
+    result
+}
+
+impl Regex {
+    pub fn parse(exp: &str) -> Result<Regex, RegexError> {
+        Regex::parse_with_limit(exp, DEFAULT_REPEAT_LIMIT)
+    }
+
+    /// 与 `parse` 相同，但 `{m,n}` 最多展开为 `repeat_limit` 个副本，嵌套时按副本数的乘积计算
+    pub fn parse_with_limit(exp: &str, repeat_limit: usize) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            exp,
+            tokens: add_connect(tokenize(exp)?),
+            index: 0,
+            repeat_limit,
+            groups: 0,
+        };
+        let regex = parser.parse_alt()?;
+        match parser.peek() {
+            None => Ok(regex),
+            Some((offset, _)) => Err(RegexError::new(
+                exp,
+                offset,
+                "unmatched `)`",
+                "an operator or the end of the expression",
+            )),
+        }
+    }
+
+    /// 运算符的优先级，数值越大结合越紧
+    fn precedence(&self) -> usize {
+        match self {
+            Regex::Alt(_) => 0,
+            Regex::Concat(_) => 1,
+            Regex::Star(_) | Regex::Plus(_) | Regex::Opt(_) | Regex::Repeat(..) => 2,
+            Regex::Empty | Regex::Char(_) | Regex::Class(_) | Regex::Group(..) => 3,
+        }
+    }
+
+    /// 输出子表达式，优先级不高于 `parent` 时加非捕获括号
+    fn fmt_child(&self, f: &mut Formatter<'_>, parent: usize) -> std::fmt::Result {
+        if self.precedence() <= parent {
+            write!(f, "(?:{})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+
+    /// 代数化简，不改变表达式所描述的语言和捕获组
+    ///
+    /// - `(a*)*`、`(a+)*`、`(a?)*`、`(a*)+` 等嵌套闭包化简为 `a*`
+    /// - 连接中的空串被删除，`ε·a` 化简为 `a`
+    /// - 嵌套的连接和或运算被展开，`(a|b)|c` 化简为 `a|b|c`
+    /// - 或运算中重复的分支被删除，相邻的单个字符的分支合并为字符类，分支的先后顺序不变
+    /// - `{0}`、`{1}`、`{0,}`、`{1,}`、`{0,1}` 化简为对应的运算符
+    ///
+    /// 捕获组只化简其内部，因此 `(?:a*)*` 会化简为 `a*`，而 `(a*)*` 保持不变
+    pub fn simplify(&self) -> Regex {
+        match self {
+            Regex::Group(index, a) => Regex::Group(*index, Box::new(a.simplify())),
+            Regex::Empty | Regex::Char(_) => self.clone(),
+            Regex::Class(set) => match set.as_slice() {
+                [range] if range.start == range.end => Regex::Char(range.start),
+                _ => self.clone(),
+            },
+            Regex::Concat(items) => {
+                let mut result = Vec::new();
+                for item in items {
+                    match item.simplify() {
+                        Regex::Empty => {}
+                        Regex::Concat(inner) => result.extend(inner),
+                        // a a* → a+
+                        Regex::Star(a) if result.last() == Some(&*a) => {
+                            result.pop();
+                            result.push(Regex::Plus(a));
+                        }
+                        item => result.push(item),
+                    }
+                }
+                match result.len() {
+                    0 => Regex::Empty,
+                    1 => result.pop().unwrap(),
+                    _ => Regex::Concat(result),
+                }
+            }
+            Regex::Alt(items) => simplify_alt(items),
+            Regex::Star(a) => match a.simplify() {
+                Regex::Empty => Regex::Empty,
+                Regex::Star(a) | Regex::Plus(a) | Regex::Opt(a) => Regex::Star(a),
+                a => Regex::Star(Box::new(a)),
+            },
+            Regex::Plus(a) => match a.simplify() {
+                Regex::Empty => Regex::Empty,
+                Regex::Star(a) | Regex::Opt(a) => Regex::Star(a),
+                Regex::Plus(a) => Regex::Plus(a),
+                a => Regex::Plus(Box::new(a)),
+            },
+            Regex::Opt(a) => match a.simplify() {
+                Regex::Empty => Regex::Empty,
+                Regex::Star(a) | Regex::Plus(a) => Regex::Star(a),
+                Regex::Opt(a) => Regex::Opt(a),
+                a => Regex::Opt(Box::new(a)),
+            },
+            Regex::Repeat(a, min, max) => match (min, max) {
+                (0, Some(0)) => Regex::Empty,
+                (1, Some(1)) => a.simplify(),
+                (0, None) => Regex::Star(a.clone()).simplify(),
+                (1, None) => Regex::Plus(a.clone()).simplify(),
+                (0, Some(1)) => Regex::Opt(a.clone()).simplify(),
+                _ => match a.simplify() {
+                    Regex::Empty => Regex::Empty,
+                    a => Regex::Repeat(Box::new(a), *min, *max),
+                },
+            },
+        }
+    }
+}
+
+/// 化简或运算：展开嵌套、去除重复分支、合并字符分支
+fn simplify_alt(items: &[Regex]) -> Regex {
+    let mut flat = Vec::new();
+    for item in items {
+        match item.simplify() {
+            Regex::Alt(inner) => flat.extend(inner),
+            item => flat.push(item),
+        }
+    }
+
+    // 只合并相邻的字符分支，并保持分支的先后顺序：查找匹配时靠前的分支优先，
+    // 把靠后的字符分支移到前面会改变 `find` 和 `captures` 的结果
+    let mut result: Vec<Regex> = Vec::new();
+    let mut class: Option<Vec<CharRange>> = None;
+    for item in flat {
+        let ranges = match item {
+            Regex::Char(c) => vec![CharRange::single(c)],
+            Regex::Class(set) => set,
+            item => {
+                if let Some(class) = class.take() {
+                    push_branch(&mut result, Regex::Class(normalize(class)).simplify());
+                }
+                push_branch(&mut result, item);
+                continue;
+            }
+        };
+        class.get_or_insert_with(Vec::new).extend(ranges);
+    }
+    if let Some(class) = class {
+        push_branch(&mut result, Regex::Class(normalize(class)).simplify());
+    }
+
+    // 只有最后一个分支为空串时才能化简为 `?`，`?` 优先尝试非空的分支
+    let optional = result.len() > 1 && result.last() == Some(&Regex::Empty);
+    if optional {
+        result.pop();
+    }
+    let result = match result.len() {
+        0 => Regex::Empty,
+        1 => result.pop().unwrap(),
+        _ => Regex::Alt(result),
+    };
+    if optional {
+        Regex::Opt(Box::new(result)).simplify()
+    } else {
+        result
+    }
+}
+
+/// 加入一个分支，与之前某个分支相同时删除：靠后的相同分支永远不会被优先选择
+fn push_branch(result: &mut Vec<Regex>, item: Regex) {
+    if !result.contains(&item) {
+        result.push(item);
+    }
+}
+
+/// 输出规范形式，重新解析后得到相同的语法树
+impl Display for Regex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Regex::Empty => write!(f, "(?:)"),
+            Regex::Group(_, a) if **a == Regex::Empty => write!(f, "()"),
+            Regex::Group(_, a) => write!(f, "({})", a),
+            Regex::Char(c) => write!(f, "{}", escape(*c, "()|*+?.[]{}\\")),
+            Regex::Class(set) => write!(f, "{}", fmt_class(set)),
+            Regex::Concat(items) => {
+                for item in items {
+                    item.fmt_child(f, 1)?;
+                }
+                Ok(())
+            }
+            Regex::Alt(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    item.fmt_child(f, 0)?;
+                }
+                Ok(())
+            }
+            Regex::Star(a) => {
+                a.fmt_child(f, 1)?;
+                write!(f, "*")
+            }
+            Regex::Plus(a) => {
+                a.fmt_child(f, 1)?;
+                write!(f, "+")
+            }
+            Regex::Opt(a) => {
+                a.fmt_child(f, 1)?;
+                write!(f, "?")
+            }
+            Regex::Repeat(a, min, max) => {
+                a.fmt_child(f, 1)?;
+                match max {
+                    Some(max) if max == min => write!(f, "{{{}}}", min),
+                    Some(max) => write!(f, "{{{},{}}}", min, max),
+                    None => write!(f, "{{{},}}", min),
+                }
+            }
+        }
+    }
+}
+
+/// 转义特殊字符和不可见字符
+fn escape(c: char, special: &str) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        _ if special.contains(c) => format!("\\{}", c),
+        _ if c.is_control() || c.is_whitespace() && c != ' ' => {
+            format!("\\u{{{:x}}}", c as u32)
+        }
+        _ => c.to_string(),
+    }
+}
+
+/// 输出字符类，取反形式更短时输出取反形式
+fn fmt_class(set: &[CharRange]) -> String {
+    if set == dot() {
+        return ".".to_string();
+    }
+    let positive = format!("[{}]", fmt_ranges(set));
+    let negative = format!("[^{}]", fmt_ranges(&complement(set)));
+    if set.is_empty() || negative.len() < positive.len() {
+        negative
+    } else {
+        positive
+    }
+}
+
+/// 输出字符类的内容，包含至少三个字符的区间写为 `a-c`
+fn fmt_ranges(ranges: &[CharRange]) -> String {
+    let special = "[]^-\\";
+    let mut result = String::new();
+    for range in ranges {
+        result.push_str(&escape(range.start, special));
+        match range.len() {
+            1 => {}
+            2 => result.push_str(&escape(range.end, special)),
+            _ => {
+                result.push('-');
+                result.push_str(&escape(range.end, special));
+            }
+        }
+    }
+    result
+}
+
+/// 递归下降解析器
+///
+/// alt := concat ('|' concat)*
+/// concat := postfix (Concat postfix)*
+/// postfix := atom ('*' | '+' | '?' | '{m,n}')*
+/// atom := char | '(' alt ')' | '(' ')' | '(?:' alt ')' | '(?:' ')'
+struct Parser<'a> {
+    exp: &'a str,
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    repeat_limit: usize,
+    /// 已经分配的捕获组个数
+    groups: usize,
+}
+
+/// 计数重复展开后子表达式被复制的最大次数，嵌套的计数重复相乘
+fn nested_copies(regex: &Regex) -> usize {
+    match regex {
+        Regex::Empty | Regex::Char(_) | Regex::Class(_) => 1,
+        Regex::Concat(items) | Regex::Alt(items) => {
+            items.iter().map(nested_copies).max().unwrap_or(1)
+        }
+        Regex::Star(r) | Regex::Plus(r) | Regex::Opt(r) | Regex::Group(_, r) => nested_copies(r),
+        Regex::Repeat(r, min, max) => max
+            .unwrap_or_else(|| min.saturating_add(1))
+            .saturating_mul(nested_copies(r)),
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens
+            .get(self.index)
+            .map(|(offset, token)| (*offset, token))
+    }
+
+    fn unexpected(&self) -> RegexError {
+        match self.peek() {
+            Some((offset, _)) => {
+                let c = self.exp[offset..].chars().next().unwrap();
+                RegexError::new(
+                    self.exp,
+                    offset,
+                    &format!("unexpected `{}`", c),
+                    "a character or `(`",
+                )
+            }
+            None => RegexError::new(
+                self.exp,
+                self.exp.len(),
+                "unexpected end of expression",
+                "a character or `(`",
+            ),
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Regex, RegexError> {
+        let mut items = vec![self.parse_concat()?];
+        while let Some((_, Token::Alt)) = self.peek() {
+            self.index += 1;
+            items.push(self.parse_concat()?);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Regex::Alt(items)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Regex, RegexError> {
+        let mut items = vec![self.parse_postfix()?];
+        while let Some((_, Token::Concat)) = self.peek() {
+            self.index += 1;
+            items.push(self.parse_postfix()?);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Regex::Concat(items)
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Regex, RegexError> {
+        let mut result = self.parse_atom()?;
+        while let Some((offset, token)) = self.peek() {
+            result = match *token {
+                Token::Star => Regex::Star(Box::new(result)),
+                Token::Plus => Regex::Plus(Box::new(result)),
+                Token::Optional => Regex::Opt(Box::new(result)),
+                Token::Repeat(min, max) => {
+                    // 嵌套的计数重复会相乘，例如 `(a{10}){10}` 展开为 100 个副本
+                    let copies = max
+                        .unwrap_or_else(|| min.saturating_add(1))
+                        .saturating_mul(nested_copies(&result));
+                    if copies > self.repeat_limit {
+                        return Err(RegexError::new(
+                            self.exp,
+                            offset,
+                            &format!(
+                                "repetition expands to {} copies, exceeding the limit of {}",
+                                copies, self.repeat_limit
+                            ),
+                            &format!("at most {} copies", self.repeat_limit),
+                        ));
+                    }
+                    Regex::Repeat(Box::new(result), min, max)
+                }
+                _ => break,
+            };
+            self.index += 1;
+        }
+        Ok(result)
+    }
+
+    fn parse_atom(&mut self) -> Result<Regex, RegexError> {
+        let (offset, token) = self.peek().ok_or_else(|| self.unexpected())?;
+        match token {
+            Token::Chars(ranges) => {
+                let result = match ranges.as_slice() {
+                    [range] if range.start == range.end => Regex::Char(range.start),
+                    _ => Regex::Class(ranges.clone()),
+                };
+                self.index += 1;
+                Ok(result)
+            }
+            Token::LParen(capture) => {
+                let group = if *capture {
+                    self.groups += 1;
+                    Some(self.groups)
+                } else {
+                    None
+                };
+                self.index += 1;
+                let result = if let Some((_, Token::RParen)) = self.peek() {
+                    Regex::Empty
+                } else {
+                    self.parse_alt()?
+                };
+                match self.peek() {
+                    Some((_, Token::RParen)) => {
+                        self.index += 1;
+                        Ok(match group {
+                            Some(index) => Regex::Group(index, Box::new(result)),
+                            None => result,
+                        })
+                    }
+                    _ => Err(RegexError::new(
+                        self.exp,
+                        self.exp.len(),
+                        &format!("unclosed `(` at offset {}", offset),
+                        "`)`",
+                    )),
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_repeat_counts_hit_the_limit() {
+        for pattern in [
+            "a{18446744073709551615}",
+            "a{18446744073709551615,}",
+            "(a{2}){18446744073709551615,}",
+        ] {
+            let err = Regex::parse(pattern).unwrap_err();
+            assert!(
+                err.message.contains("exceeding the limit"),
+                "{}: {}",
+                pattern,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn multi_char_escapes_are_not_range_ends() {
+        for pattern in [r"[!-\d]", r"[a-\w]", r"[a-\p{L}]"] {
+            let err = Regex::parse(pattern).unwrap_err();
+            assert_eq!(err.message, "invalid range end", "{}", pattern);
+            assert_eq!(err.offset, 3, "{}", pattern);
+        }
+        assert_eq!(
+            Regex::parse(r"[!-\u{7e}]").unwrap(),
+            Regex::Class(vec![CharRange::new('!', '~')])
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::charset::{partition, CharRange};
+use crate::dfa::DFA;
+use crate::graph::{Graph_, Label};
+use crate::nfa::NFA;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    NFA,
+    DFA,
+}
+
+/// 一条转移，`range` 为 `None` 时是 ε 转移
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: usize,
+    pub to: usize,
+    pub range: Option<[char; 2]>,
+}
+
+/// 与实现无关的自动机描述，用于保存为 JSON 或文本表格以及重新加载
+///
+/// NFA 的 `tags` 为捕获组的槽位，DFA 的 `tags` 为接受状态对应的模式编号；
+/// `alphabet` 为所有边上区间的等价类，只用于阅读和比较，加载时不使用
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Automaton {
+    pub kind: Kind,
+    pub states: usize,
+    pub alphabet: Vec<[char; 2]>,
+    pub start: usize,
+    pub accept: Vec<usize>,
+    pub tags: BTreeMap<usize, usize>,
+    pub transitions: Vec<Transition>,
+}
+
+impl Automaton {
+    fn from_graph(
+        kind: Kind,
+        graph: &Graph_,
+        start: usize,
+        accept: &[usize],
+        tags: &HashMap<usize, usize>,
+    ) -> Self {
+        let mut transitions = Vec::new();
+        for (from, edges) in graph.edges.iter().enumerate() {
+            for &(to, label) in edges {
+                transitions.push(Transition {
+                    from,
+                    to,
+                    range: label.range().map(|range| [range.start, range.end]),
+                });
+            }
+        }
+        let mut accept = accept.to_vec();
+        accept.sort();
+        Automaton {
+            kind,
+            states: graph.edges.len(),
+            alphabet: graph
+                .get_terminal()
+                .into_iter()
+                .map(|range| [range.start, range.end])
+                .collect(),
+            start,
+            accept,
+            tags: tags.iter().map(|(&state, &tag)| (state, tag)).collect(),
+            transitions,
+        }
+    }
+
+    /// 检查编号是否越界并重建 `Graph_`，`tags` 不写入 `Graph_`
+    fn to_graph(&self, kind: Kind) -> Result<Graph_, String> {
+        if self.kind != kind {
+            return Err(format!("expected a {:?}, found a {:?}", kind, self.kind));
+        }
+        let check = |state: usize| {
+            if state < self.states {
+                Ok(state)
+            } else {
+                Err(format!(
+                    "state {} is out of range, there are {} states",
+                    state, self.states
+                ))
+            }
+        };
+        check(self.start)?;
+        for &state in self.accept.iter().chain(self.tags.keys()) {
+            check(state)?;
+        }
+
+        let mut graph = Graph_::new(self.states);
+        for transition in &self.transitions {
+            let label = match transition.range {
+                None => Label::Epsilon,
+                Some([start, end]) if start <= end => Label::from(CharRange::new(start, end)),
+                Some([start, end]) => {
+                    return Err(format!("invalid range {:?}-{:?}", start, end));
+                }
+            };
+            if kind == Kind::DFA && label == Label::Epsilon {
+                return Err(format!(
+                    "ε transition from state {} in a DFA",
+                    transition.from
+                ));
+            }
+            graph.add_edge(check(transition.from)?, check(transition.to)?, label);
+        }
+        Ok(graph)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| err.to_string())
+    }
+
+    /// 按行输出的文本表格，每行一个字段或一条转移，字符使用 Rust 的字符字面量表示
+    ///
+    /// ```text
+    /// kind dfa
+    /// states 2
+    /// alphabet 'a'-'z'
+    /// start 0
+    /// accept 1
+    /// tag 1 0
+    /// edge 0 1 'a'-'z'
+    /// edge 1 1 ε
+    /// ```
+    pub fn to_text(&self) -> String {
+        let kind = match self.kind {
+            Kind::NFA => "nfa",
+            Kind::DFA => "dfa",
+        };
+        let mut text = String::new();
+        writeln!(text, "kind {}", kind).unwrap();
+        writeln!(text, "states {}", self.states).unwrap();
+        let alphabet: Vec<String> = self
+            .alphabet
+            .iter()
+            .map(|&range| fmt_range(range))
+            .collect();
+        writeln!(text, "alphabet {}", alphabet.join(" ")).unwrap();
+        writeln!(text, "start {}", self.start).unwrap();
+        let accept: Vec<String> = self.accept.iter().map(usize::to_string).collect();
+        writeln!(text, "accept {}", accept.join(" ")).unwrap();
+        for (state, tag) in &self.tags {
+            writeln!(text, "tag {} {}", state, tag).unwrap();
+        }
+        for transition in &self.transitions {
+            let label = match transition.range {
+                None => "ε".to_string(),
+                Some(range) => fmt_range(range),
+            };
+            writeln!(text, "edge {} {} {}", transition.from, transition.to, label).unwrap();
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut kind = None;
+        let mut states = None;
+        let mut start = None;
+        let mut accept = Vec::new();
+        let mut tags = BTreeMap::new();
+        let mut transitions = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let numbers = || -> Result<Vec<usize>, String> {
+                rest.split_whitespace()
+                    .map(|x| {
+                        x.parse()
+                            .map_err(|_| error(&format!("invalid number `{}`", x)))
+                    })
+                    .collect()
+            };
+            match key {
+                "kind" => {
+                    kind = Some(match rest {
+                        "nfa" => Kind::NFA,
+                        "dfa" => Kind::DFA,
+                        _ => return Err(error("expected `nfa` or `dfa`")),
+                    })
+                }
+                "states" => states = numbers()?.first().copied(),
+                "alphabet" => {}
+                "start" => start = numbers()?.first().copied(),
+                "accept" => accept = numbers()?,
+                "tag" => match numbers()?.as_slice() {
+                    &[state, tag] => {
+                        tags.insert(state, tag);
+                    }
+                    _ => return Err(error("expected `tag <state> <tag>`")),
+                },
+                "edge" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let mut number = || {
+                        parts
+                            .next()
+                            .and_then(|x| x.parse().ok())
+                            .ok_or_else(|| error("expected `edge <from> <to> <label>`"))
+                    };
+                    let (from, to) = (number()?, number()?);
+                    let label = parts.next().unwrap_or("");
+                    let range = if label == "ε" {
+                        None
+                    } else {
+                        Some(parse_range(label).ok_or_else(|| error("invalid label"))?)
+                    };
+                    transitions.push(Transition { from, to, range });
+                }
+                _ => return Err(error(&format!("unknown field `{}`", key))),
+            }
+        }
+
+        let alphabet: Vec<CharRange> = transitions
+            .iter()
+            .filter_map(|transition| transition.range)
+            .filter(|[start, end]| start <= end)
+            .map(|[start, end]| CharRange::new(start, end))
+            .collect();
+        Ok(Automaton {
+            kind: kind.ok_or("missing field `kind`")?,
+            states: states.ok_or("missing field `states`")?,
+            alphabet: partition(&alphabet)
+                .into_iter()
+                .map(|range| [range.start, range.end])
+                .collect(),
+            start: start.ok_or("missing field `start`")?,
+            accept,
+            tags,
+            transitions,
+        })
+    }
+}
+
+/// 区间写为 `'a'` 或 `'a'-'z'`
+fn fmt_range([start, end]: [char; 2]) -> String {
+    if start == end {
+        format!("{:?}", start)
+    } else {
+        format!("{:?}-{:?}", start, end)
+    }
+}
+
+/// 解析 `fmt_range` 输出的区间
+fn parse_range(label: &str) -> Option<[char; 2]> {
+    let (start, rest) = parse_char(label)?;
+    if rest.is_empty() {
+        return Some([start, start]);
+    }
+    let (end, rest) = parse_char(rest.strip_prefix('-')?)?;
+    rest.is_empty().then_some([start, end])
+}
+
+/// 解析一个 Rust 字符字面量，返回字符和剩余的部分
+fn parse_char(text: &str) -> Option<(char, &str)> {
+    let text = text.strip_prefix('\'')?;
+    let mut chars = text.chars();
+    let c = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (hex, rest) = rest.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+            }
+            c => c,
+        },
+        c => c,
+    };
+    let rest = chars.as_str().strip_prefix('\'')?;
+    Some((c, rest))
+}
+
+impl NFA {
+    pub fn to_automaton(&self) -> Automaton {
+        let accept = [self.graph.edges.len() - 1];
+        Automaton::from_graph(Kind::NFA, &self.graph, 0, &accept, &self.graph.tags)
+    }
+
+    /// 起点必须为 0，唯一的终点必须为最后一个状态
+    pub fn from_automaton(automaton: &Automaton) -> Result<NFA, String> {
+        let mut graph = automaton.to_graph(Kind::NFA)?;
+        if automaton.start != 0 || automaton.accept != [automaton.states - 1] {
+            return Err("an nfa must start at state 0 and accept only its last state".to_string());
+        }
+        graph.tags = automaton
+            .tags
+            .iter()
+            .map(|(&state, &tag)| (state, tag))
+            .collect();
+        Ok(NFA { graph })
+    }
+}
+
+impl DFA {
+    pub fn to_automaton(&self) -> Automaton {
+        Automaton::from_graph(
+            Kind::DFA,
+            &self.graph,
+            self.start_state,
+            &self.end_states,
+            &self.accept_tags,
+        )
+    }
+
+    /// 接受状态没有模式编号时使用模式 0
+    pub fn from_automaton(automaton: &Automaton) -> Result<DFA, String> {
+        let graph = automaton.to_graph(Kind::DFA)?;
+        for (from, edges) in graph.edges.iter().enumerate() {
+            let mut ranges: Vec<CharRange> = edges.iter().filter_map(|(_, l)| l.range()).collect();
+            ranges.sort_by_key(|range| range.start);
+            if ranges.windows(2).any(|pair| pair[0].end >= pair[1].start) {
+                return Err(format!("state {} has overlapping transitions", from));
+            }
+        }
+        Ok(DFA {
+            start_state: automaton.start,
+            end_states: automaton.accept.clone(),
+            accept_tags: automaton
+                .accept
+                .iter()
+                .map(|&state| (state, automaton.tags.get(&state).copied().unwrap_or(0)))
+                .collect(),
+            graph,
+            nfa_states: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_dfa_transitions_are_rejected() {
+        let text = "kind dfa\nstates 3\nstart 0\naccept 1 2\nedge 0 1 'a'-'b'\nedge 0 2 'b'";
+        let automaton = Automaton::from_text(text).unwrap();
+        let err = DFA::from_automaton(&automaton).err().unwrap();
+        assert_eq!(err, "state 0 has overlapping transitions");
+    }
+}